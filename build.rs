@@ -0,0 +1,9 @@
+use std::env;
+
+fn main() {
+    // Only the functions listed here are callable from JS.
+    if env::var("CARGO_CFG_TARGET_OS").as_ref().map(String::as_str) == Ok("emscripten") {
+        println!("cargo:rustc-link-arg=-s");
+        println!("cargo:rustc-link-arg=EXPORTED_FUNCTIONS=['_process_web','_free_cstring_web']");
+    }
+}
@@ -0,0 +1,165 @@
+//! Renders many tiles at once.
+//!
+//! Tiles are stored in a directory tree `{z}/{x}/{y}.mvt`
+//! and are rendered on all CPU cores.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use projection::TileAddress;
+
+/// File extensions of vector tiles in a directory tree.
+const EXTENSIONS: &[&str] = &["mvt", "pbf"];
+
+/// The tiles found in a directory tree.
+#[derive(Debug, Default)]
+pub struct FoundTiles {
+    /// The tiles sorted by their address.
+    pub tiles: Vec<(TileAddress, PathBuf)>,
+    /// Directories that could not be read and tiles that have the
+    /// address of another tile.
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+/// Finds all tiles in a `{z}/{x}/{y}.mvt` directory tree.
+///
+/// Files and directories that do not fit the scheme are ignored.
+/// Fails only if the root directory can not be read.
+pub fn find_tiles(root: &Path) -> io::Result<FoundTiles> {
+    let mut found = FoundTiles::default();
+    let mut tiles = Vec::new();
+    for (z, z_path) in numbered_entries(root, true)? {
+        for (x, x_path) in entries_or_error(&z_path, true, &mut found.errors) {
+            for (y, path) in entries_or_error(&x_path, false, &mut found.errors) {
+                if z > 31 {
+                    continue;
+                }
+                if let Some(address) = TileAddress::new(z as u8, x, y) {
+                    tiles.push((address, path));
+                }
+            }
+        }
+    }
+    tiles.sort_by(|a, b| (a.0.z, a.0.x, a.0.y, &a.1).cmp(&(b.0.z, b.0.x, b.0.y, &b.1)));
+    // `{y}.mvt` and `{y}.pbf` would be rendered to the same file.
+    for (address, path) in tiles {
+        match found.tiles.last() {
+            Some(&(last, ref first)) if last == address => {
+                let message = format!("{} has the same tile address", first.display());
+                found.errors.push((path, io::Error::new(io::ErrorKind::AlreadyExists, message)));
+            },
+            _ => found.tiles.push((address, path)),
+        }
+    }
+    Ok(found)
+}
+
+/// Like `numbered_entries`, but records the error and returns no entries.
+fn entries_or_error(dir: &Path, directories: bool, errors: &mut Vec<(PathBuf, io::Error)>)
+        -> Vec<(u32, PathBuf)> {
+    numbered_entries(dir, directories).unwrap_or_else(|e| {
+        errors.push((dir.to_owned(), e));
+        Vec::new()
+    })
+}
+
+/// Lists the directories or tile files in `dir` named with a number.
+fn numbered_entries(dir: &Path, directories: bool) -> io::Result<Vec<(u32, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() != directories {
+            continue;
+        }
+        let name = if directories {
+            path.file_name()
+        } else {
+            match path.extension().and_then(|e| e.to_str()) {
+                Some(extension) if EXTENSIONS.contains(&extension) => path.file_stem(),
+                _ => continue,
+            }
+        };
+        if let Some(n) = name.and_then(|n| n.to_str()).and_then(|n| n.parse().ok()) {
+            entries.push((n, path));
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the path of a tile in a `{z}/{x}/{y}.{extension}` directory tree.
+pub fn tile_path(root: &Path, address: TileAddress, extension: &str) -> PathBuf {
+    root.join(address.z.to_string())
+        .join(address.x.to_string())
+        .join(format!("{}.{}", address.y, extension))
+}
+
+/// Returns the number of threads to use for rendering.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Applies `f` to all items using the given number of threads.
+///
+/// Every thread calls `init` once to create its own state which is passed to `f`.
+/// The results are in the same order as the items.
+pub fn run_parallel<T, S, R, I, F>(items: &[T], threads: usize, init: I, f: F) -> Vec<R>
+    where T: Sync, R: Send, I: Fn() -> S + Sync, F: Fn(&mut S, &T) -> R + Sync
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1).min(items.len()) {
+            scope.spawn(|| {
+                let mut state = init();
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let item = match items.get(i) {
+                        Some(item) => item,
+                        None => break,
+                    };
+                    let result = f(&mut state, item);
+                    results.lock().expect("no thread panicked").push((i, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().expect("no thread panicked");
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[test]
+fn test_find_tiles() {
+    use std::env;
+    use std::fs::File;
+
+    let root = env::temp_dir().join(format!("colorful-map-find-tiles-{}", ::std::process::id()));
+    for path in &["0/0/0.mvt", "0/0/0.pbf", "1/1/0.pbf", "1/0/1.mvt", "1/0/7.mvt", "1/0/notes.txt",
+                  "a/0/0.mvt"] {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap();
+    }
+    let FoundTiles { tiles, errors } = find_tiles(&root).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, root.join("0/0/0.pbf"));
+    let addresses: Vec<_> = tiles.iter().map(|&(address, _)| address).collect();
+    assert_eq!(addresses, vec![
+        TileAddress { z: 0, x: 0, y: 0 },
+        TileAddress { z: 1, x: 0, y: 1 },
+        TileAddress { z: 1, x: 1, y: 0 },
+    ]);
+    assert_eq!(tiles[2].1, tile_path(&root, addresses[2], "pbf"));
+}
+
+#[test]
+fn test_run_parallel() {
+    let items: Vec<u32> = (0..100).collect();
+    let squares = run_parallel(&items, 4, || (), |_, &n| n * n);
+    assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<_>>());
+}
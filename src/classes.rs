@@ -0,0 +1,402 @@
+//! Maps tags to the classes and attributes of SVG elements.
+//!
+//! The stylesheet selects elements by their classes. Which tags become
+//! classes depends on the schema of the tiles: Tilezen uses `kind`,
+//! OpenMapTiles `class` and `subclass`, Mapbox Streets `type`. A
+//! `ClassMapping` describes this in a JSON (or TOML) document:
+//!
+//! ```json
+//! {
+//!     "classes": [
+//!         {"tag": "$layer", "prefix": "layer-"},
+//!         {"tag": "class", "prefix": "kind-", "default": "unknown",
+//!          "map": {"motorway": "highway", "primary": "major_road"}},
+//!         {"tag": "brunnel", "prefix": "is_", "map": {"ford": null}},
+//!         {"tag": "rank", "prefix": "min-zoom-", "default": "0", "transform": ["floor"]}
+//!     ],
+//!     "attributes": [
+//!         {"tag": "name", "name": "data-name"}
+//!     ]
+//! }
+//! ```
+//!
+//! Each rule reads a tag, or with `$layer`, `$type` and `$id` the layer
+//! name, geometry type and id of the feature. The value is looked up in
+//! `map` (a `null` drops it), then the `transform`s are applied in order:
+//! `lowercase`, `uppercase`, `floor` and `round`. Missing values are
+//! replaced by `default`; without a default the rule is skipped, or the
+//! feature fails if the rule is `required`.
+//!
+//! A class rule adds `prefix` followed by the value as class. With
+//! `flag` it adds the fixed class `flag` if the value is true or a
+//! non-zero number instead. An attribute rule writes the value to the
+//! attribute `name`, which must start with `data-`.
+
+use std::collections::HashMap;
+
+use serde_json::{self, Map, Value as Json};
+
+use error::{Error, Result};
+use escape::ClassToken;
+use feature::Feature;
+use tag::Value;
+use vector_tile::Tile_GeomType as GeomType;
+
+/// Changes a value before it is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Converts letters to lowercase.
+    Lowercase,
+    /// Converts letters to uppercase.
+    Uppercase,
+    /// Rounds numbers down, other values are dropped.
+    Floor,
+    /// Rounds numbers to the nearest integer, other values are dropped.
+    Round,
+}
+
+/// Where a rule writes the value.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    /// A class consisting of a prefix and the value.
+    Class(String),
+    /// A fixed class if the value is trueish.
+    Flag(String),
+    /// An attribute with this name.
+    Attribute(String),
+}
+
+/// Turns one tag into a class or attribute.
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    tag: String,
+    target: Target,
+    map: HashMap<String, Option<String>>,
+    transforms: Vec<Transform>,
+    default: Option<String>,
+    required: bool,
+}
+
+/// Describes which tags become classes and attributes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassMapping {
+    classes: Vec<Rule>,
+    attributes: Vec<Rule>,
+}
+
+/// A value produced by a rule.
+enum Found<'a> {
+    Tag(&'a Value<'a>),
+    Text(String),
+}
+
+impl<'a> Found<'a> {
+    fn yes(&self) -> bool {
+        match *self {
+            Found::Tag(value) => value.yes(),
+            Found::Text(ref text) => text == "true" || text.parse::<f64>().is_ok_and(|x| x != 0.0),
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match *self {
+            Found::Tag(value) => value.number(),
+            Found::Text(ref text) => text.parse().ok(),
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            Found::Tag(value) => value.to_string(),
+            Found::Text(text) => text,
+        }
+    }
+}
+
+impl ClassMapping {
+    /// Reads a mapping from JSON.
+    pub fn from_json(json: &str) -> Result<ClassMapping> {
+        ClassMapping::from_value(&serde_json::from_str(json).map_err(Error::Json)?)
+    }
+
+    /// Reads a mapping from TOML with the structure of the JSON documents.
+    ///
+    /// Rules are written as arrays of tables, `[[classes]]` and
+    /// `[[attributes]]`. TOML has no `null`, use `false` in `map` to
+    /// drop a value.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<ClassMapping> {
+        let document: ::toml::Value = ::toml::from_str(toml).map_err(Error::Toml)?;
+        ClassMapping::from_value(&serde_json::to_value(document).map_err(Error::Json)?)
+    }
+
+    /// Reads a mapping from a parsed JSON document.
+    pub fn from_value(json: &Json) -> Result<ClassMapping> {
+        let document = json.as_object()
+            .ok_or(Error::InvalidMapping("a mapping must be an object"))?;
+        let rules = |key| match document.get(key) {
+            None => Ok(Vec::new()),
+            Some(Json::Array(rules)) => rules.iter().map(|rule| match *rule {
+                Json::Object(ref rule) => Rule::from_json(rule, key == "attributes"),
+                _ => Err(Error::InvalidMapping("a rule must be an object")),
+            }).collect(),
+            Some(_) => Err(Error::InvalidMapping("rules must be in an array")),
+        };
+        let mapping = ClassMapping {
+            classes: rules("classes")?,
+            attributes: rules("attributes")?,
+        };
+        // Repeated attributes are not allowed in XML.
+        for (i, rule) in mapping.attributes.iter().enumerate() {
+            if mapping.attributes[..i].iter().any(|other| other.target == rule.target) {
+                return Err(Error::InvalidMapping("an attribute is written twice"));
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// Returns the classes of a feature.
+    ///
+    /// The classes are sanitized with `ClassToken`.
+    pub fn classes(&self, feature: &Feature) -> Result<Vec<String>> {
+        let mut classes = Vec::new();
+        for rule in &self.classes {
+            let found = match rule.find(feature)? {
+                Some(found) => found,
+                None => continue,
+            };
+            // Note: values come from the tile and are sanitized
+            // so they can neither leave the attribute nor add classes.
+            match rule.target {
+                Target::Class(ref prefix) => classes.push(
+                    ClassToken(&format!("{}{}", prefix, found.into_text())).to_string()),
+                Target::Flag(ref class) if found.yes() => classes.push(ClassToken(class).to_string()),
+                Target::Flag(_) | Target::Attribute(_) => (),
+            }
+        }
+        Ok(classes)
+    }
+
+    /// Returns the names and values of the attributes of a feature.
+    ///
+    /// The values are not escaped.
+    pub fn attributes(&self, feature: &Feature) -> Result<Vec<(&str, String)>> {
+        let mut attributes = Vec::new();
+        for rule in &self.attributes {
+            if let (Some(found), Target::Attribute(ref name)) = (rule.find(feature)?, &rule.target) {
+                attributes.push((name.as_str(), found.into_text()));
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+/// The classes of the web map's stylesheet for Tilezen tiles.
+///
+/// Features without a `kind` tag get the class `kind-unknown`.
+impl Default for ClassMapping {
+    fn default() -> ClassMapping {
+        let class = |tag: &str, target| Rule {
+            tag: tag.to_owned(),
+            target,
+            map: HashMap::new(),
+            transforms: Vec::new(),
+            default: None,
+            required: false,
+        };
+        let flag = |tag: &str| class(tag, Target::Flag(tag.to_owned()));
+        ClassMapping {
+            classes: vec![
+                class("$layer", Target::Class("layer-".to_owned())),
+                Rule {
+                    default: Some("unknown".to_owned()),
+                    ..class("kind", Target::Class("kind-".to_owned()))
+                },
+                flag("boundary"),
+                flag("is_tunnel"),
+                flag("is_bridge"),
+                Rule {
+                    transforms: vec![Transform::Floor],
+                    default: Some("0".to_owned()),
+                    ..class("min_zoom", Target::Class("min-zoom-".to_owned()))
+                },
+            ],
+            attributes: Vec::new(),
+        }
+    }
+}
+
+impl Rule {
+    fn from_json(json: &Map<String, Json>, attribute: bool) -> Result<Rule> {
+        let string = |key| match json.get(key) {
+            None => Ok(None),
+            Some(Json::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(Error::InvalidMapping("expected a string")),
+        };
+        let tag = string("tag")?.ok_or(Error::InvalidMapping("a rule needs a tag"))?;
+        let target = if attribute {
+            let name = string("name")?.ok_or(Error::InvalidMapping("an attribute needs a name"))?;
+            let valid = name.starts_with("data-") && name.len() > 5
+                && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+            // `data-id` and `data-tag-*` are written by the painter.
+            if !valid || name == "data-id" || name.starts_with("data-tag-") {
+                return Err(Error::InvalidMapping("attribute names must be `data-` and lowercase letters"));
+            }
+            Target::Attribute(name)
+        } else {
+            match (string("prefix")?, string("flag")?) {
+                (_, Some(flag)) => Target::Flag(flag),
+                (prefix, None) => Target::Class(prefix.unwrap_or_default()),
+            }
+        };
+        let map = match json.get("map") {
+            None => HashMap::new(),
+            Some(Json::Object(map)) => map.iter().map(|(from, to)| match *to {
+                Json::String(ref to) => Ok((from.clone(), Some(to.clone()))),
+                Json::Null | Json::Bool(false) => Ok((from.clone(), None)),
+                _ => Err(Error::InvalidMapping("`map` values must be strings or null")),
+            }).collect::<Result<_>>()?,
+            Some(_) => return Err(Error::InvalidMapping("`map` must be an object")),
+        };
+        let transforms = match json.get("transform") {
+            None => Vec::new(),
+            Some(Json::Array(transforms)) => transforms.iter().map(|t| match t.as_str() {
+                Some("lowercase") => Ok(Transform::Lowercase),
+                Some("uppercase") => Ok(Transform::Uppercase),
+                Some("floor") => Ok(Transform::Floor),
+                Some("round") => Ok(Transform::Round),
+                _ => Err(Error::InvalidMapping("unknown transform")),
+            }).collect::<Result<_>>()?,
+            Some(_) => return Err(Error::InvalidMapping("`transform` must be an array")),
+        };
+        Ok(Rule {
+            tag,
+            target,
+            map,
+            transforms,
+            default: string("default")?,
+            required: json.get("required").and_then(Json::as_bool).unwrap_or(false),
+        })
+    }
+
+    /// Reads and transforms the value of the rule.
+    fn find<'a>(&self, feature: &'a Feature) -> Result<Option<Found<'a>>> {
+        let found = match self.tag.as_str() {
+            "$layer" => Some(Found::Text(feature.layer().to_owned())),
+            "$type" => match feature.geom_type() {
+                GeomType::POINT => Some(Found::Text("Point".to_owned())),
+                GeomType::LINESTRING => Some(Found::Text("LineString".to_owned())),
+                GeomType::POLYGON => Some(Found::Text("Polygon".to_owned())),
+                GeomType::UNKNOWN => None,
+            },
+            "$id" => feature.id().map(|id| Found::Text(id.to_string())),
+            key => feature.tags().get(key).map(Found::Tag),
+        };
+        let found = match found {
+            Some(found) if !self.map.is_empty() => {
+                let text = found.into_text();
+                match self.map.get(&text) {
+                    Some(mapped) => mapped.clone().map(Found::Text),
+                    None => Some(Found::Text(text)),
+                }
+            },
+            found => found,
+        };
+        let mut found = found;
+        for &transform in &self.transforms {
+            found = found.and_then(|found| Some(match transform {
+                Transform::Lowercase => Found::Text(found.into_text().to_lowercase()),
+                Transform::Uppercase => Found::Text(found.into_text().to_uppercase()),
+                Transform::Floor => Found::Text(found.number()?.floor().to_string()),
+                Transform::Round => Found::Text(found.number()?.round().to_string()),
+            }));
+        }
+        match (found, &self.default) {
+            (Some(found), _) => Ok(Some(found)),
+            (None, Some(default)) => Ok(Some(Found::Text(default.clone()))),
+            (None, None) if self.required => Err(Error::MissingTag(self.tag.clone())),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[test]
+fn test_class_mapping() {
+    use tag::TagMap;
+    use vector_tile::Tile_Feature;
+
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_field_type(GeomType::LINESTRING);
+    raw_feature.set_id(3);
+    let mut tags = TagMap::new();
+    tags.insert("class", Value::String("Motorway"));
+    tags.insert("brunnel", Value::String("bridge"));
+    tags.insert("rank", Value::Float64(11.7));
+    tags.insert("name", Value::String("A \"1\""));
+    tags.insert("kind", Value::String("highway"));
+    tags.insert("min_zoom", Value::Int64(12));
+    tags.insert("is_bridge", Value::Bool(true));
+    tags.insert("is_tunnel", Value::Bool(false));
+    let feature = Feature::new(&raw_feature, tags, "transportation", 2, 1.0).unwrap();
+    let write = |mapping: &ClassMapping| -> Result<String> {
+        let mut out = mapping.classes(&feature)?.join(" ");
+        for (name, value) in mapping.attributes(&feature)? {
+            out.push_str(&format!(" {}={}", name, value));
+        }
+        Ok(out)
+    };
+
+    assert_eq!(write(&ClassMapping::default()).unwrap(),
+        "layer-transportation kind-highway is_bridge min-zoom-12");
+    let mapping = ClassMapping::from_json(r#"{
+        "classes": [
+            {"tag": "$layer", "prefix": "layer-"},
+            {"tag": "class", "prefix": "kind-", "transform": ["lowercase"],
+             "map": {"Motorway": "highway"}},
+            {"tag": "subclass", "prefix": "sub-", "default": "none x"},
+            {"tag": "brunnel", "prefix": "is_", "map": {"ford": null}},
+            {"tag": "$type", "flag": "never"},
+            {"tag": "rank", "prefix": "min-zoom-", "transform": ["floor"]},
+            {"tag": "name", "prefix": "name-", "transform": ["round"]}
+        ],
+        "attributes": [
+            {"tag": "name", "name": "data-name"},
+            {"tag": "$id", "name": "data-feature"}
+        ]
+    }"#).unwrap();
+    assert_eq!(write(&mapping).unwrap(),
+        "layer-transportation kind-highway sub-none_x is_bridge min-zoom-11 \
+         data-name=A \"1\" data-feature=3");
+
+    let required = ClassMapping::from_json(r#"{"classes": [{"tag": "type", "required": true}]}"#);
+    match write(&required.unwrap()) {
+        Err(Error::MissingTag(ref key)) if key == "type" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(write(&ClassMapping::from_json("{}").unwrap()).unwrap(), "");
+    assert!(ClassMapping::from_json(r#"{"attributes": [{"tag": "x", "name": "onload"}]}"#).is_err());
+    assert!(ClassMapping::from_json(r#"{"attributes": [{"tag": "x", "name": "data-x"},
+                                                       {"tag": "y", "name": "data-x"}]}"#).is_err());
+    assert!(ClassMapping::from_json(r#"{"classes": [{"tag": "x", "transform": ["reverse"]}]}"#).is_err());
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn test_class_mapping_toml() {
+    let toml = r#"
+        [[classes]]
+        tag = "class"
+        prefix = "kind-"
+        map = { ford = false }
+
+        [[attributes]]
+        tag = "name"
+        name = "data-name"
+    "#;
+    let json = r#"{
+        "classes": [{"tag": "class", "prefix": "kind-", "map": {"ford": null}}],
+        "attributes": [{"tag": "name", "name": "data-name"}]
+    }"#;
+    assert_eq!(ClassMapping::from_toml(toml).unwrap(), ClassMapping::from_json(json).unwrap());
+    assert!(ClassMapping::from_toml("classes = 1").is_err());
+}
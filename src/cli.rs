@@ -0,0 +1,740 @@
+//! The command line interface.
+//!
+//! Renders map tiles without a browser:
+//!
+//! ```text
+//! colorful-map render tile.mvt -o tile.svg --z 12 --x 2138 --y 1388
+//! colorful-map render tile.mvt -o tile.png --format png --size 512
+//! colorful-map batch tiles/ svg/
+//! colorful-map batch tiles.mbtiles svg/
+//! colorful-map batch tiles.pmtiles svg/
+//! colorful-map tile overlay.geojson overlay.mbtiles --max-zoom 12
+//! colorful-map validate tile.mvt
+//! ```
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use batch;
+use classes::ClassMapping;
+use document::{self, Stylesheet};
+use error::Error;
+use filter::Filter;
+use geojson::{self, Coordinates};
+use glstyle::GlStyle;
+#[cfg(feature = "mbtiles")]
+use mbtiles::MbTiles;
+use options::{DataTags, Options};
+use pmtiles::PmTiles;
+use projection::TileAddress;
+use {paint, process_with_options};
+#[cfg(feature = "raster")]
+use raster::RasterPainter;
+#[cfg(feature = "mbtiles")]
+use serde_json;
+use svg::{StyleOutput, SvgPainter};
+use tiler::{self, TileOptions};
+use validate::{self, Severity};
+
+const USAGE: &str = "\
+Usage: colorful-map render [options] [INPUT]
+       colorful-map batch [options] INPUT OUTPUT_DIR
+       colorful-map tile [options] GEOJSON OUTPUT
+       colorful-map validate [options] [INPUT]
+
+`render` renders a Mapbox Vector Tile as a standalone SVG document,
+a PNG image or GeoJSON. It reads from standard input if INPUT is missing
+or `-`.
+If INPUT is an MBTiles or PMTiles archive the tile address must be given.
+
+`batch` renders all tiles in a `{z}/{x}/{y}.mvt` directory tree or
+an MBTiles or PMTiles archive to a tree of `{z}/{x}/{y}.svg` files (or
+`.png` and `.geojson` files with `--format`) using all CPU cores.
+
+`tile` cuts a GeoJSON file into vector tiles and writes them to a
+`{z}/{x}/{y}.mvt` directory tree or, if OUTPUT ends with `.mbtiles`,
+to an MBTiles archive.
+
+`validate` checks a tile against the Mapbox Vector Tile specification 2.1
+and prints every violation. It fails if there are errors, warnings alone
+are not fatal. INPUT is read like in `render`.
+
+Options (each command rejects those that do not apply to it):
+    -o, --output FILE       write to FILE instead of standard output (render)
+    --z Z, --x X, --y Y     the address of the tile (render, validate)
+    --format FORMAT         `svg` (default), `png` or `geojson` (render, batch)
+                            GeoJSON uses longitude and latitude if the address is given
+    --size N                the width and height of PNG images, 256 (default) or 512
+                            (render, batch)
+    -j, --jobs N            use N threads instead of one per CPU core (batch)
+    --stylesheet URL        link to a stylesheet instead of embedding the default one
+                            (render, batch)
+    --no-stylesheet         neither embed nor link a stylesheet (render, batch)
+    --gl-style FILE         style the tile with a Mapbox GL style (render, batch)
+                            instead of the default stylesheet, needs the tile address
+    --gl-style-classes      write the GL style as embedded stylesheet with a class
+                            per style layer instead of inline styles
+    --lenient               skip invalid features and layers instead of failing,
+                            implies `--skip-unknown-versions` (render, batch)
+    --skip-unknown-versions skip layers with unknown versions instead of failing
+                            (render, batch)
+    --id-tag KEY            use the tag KEY as id of features without an id
+                            (render, batch)
+    --duplicate-ids         warn about features sharing an id within a layer
+                            (render, batch)
+    --filter EXPR           only draw features matching EXPR (render, batch; not GeoJSON),
+                            e.g. `kind in (major_road, highway) && min_zoom <= 12`
+    --classes FILE          map tags to classes and attributes as described in the
+                            JSON or, if FILE ends with `.toml`, TOML file (render, batch;
+                            not with `--gl-style`)
+    --data-tags KEYS        write the tags KEYS (comma separated, `*` for all) as
+                            `data-tag-*` attributes (render, batch)
+    --only-layers A,B       only draw the layers with these names (render, batch)
+    --exclude-layers A,B    do not draw the layers with these names (render, batch)
+    --layer-order A,B       draw these layers in this order, each above the ones before,
+                            instead of mixing all layers by sort rank (render, batch)
+    --sort-rank LAYER=N     the sort rank of features of LAYER without a `sort_rank` tag
+                            (render, batch, can be repeated)
+    --no-decompress         do not decompress gzip or zlib compressed tiles (render,
+                            batch, validate)
+    --min-zoom Z            the lowest zoom level to create tiles for (tile, default 0)
+    --max-zoom Z            the highest zoom level to create tiles for (tile, default 14)
+    --layer NAME            the name of the layer (tile, default the file name)
+    -h, --help              print this help
+";
+
+/// Errors reported to the user.
+#[derive(Debug)]
+enum CliError {
+    /// The command line is invalid.
+    Usage(String),
+    /// A file could not be read or written.
+    Io(String, io::Error),
+    /// The tile could not be rendered.
+    Tile(String, Error),
+    /// An archive does not contain the requested tile.
+    NoTile(String, TileAddress),
+    /// A tile violates the specification.
+    Invalid(String, usize),
+    /// Some tiles of a batch could not be rendered.
+    Batch(usize, usize),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Usage(ref message) =>
+                write!(f, "{}\nTry `colorful-map --help` for more information.", message),
+            CliError::Io(ref path, ref e) => write!(f, "{}: {}", path, e),
+            CliError::Tile(ref path, ref e) => write!(f, "{}: {}", path, e),
+            CliError::NoTile(ref path, address) =>
+                write!(f, "{}: there is no tile {}/{}/{}", path, address.z, address.x, address.y),
+            CliError::Invalid(ref path, errors) =>
+                write!(f, "{}: {} errors", path, errors),
+            CliError::Batch(failed, total) => write!(f, "{} of {} tiles failed", failed, total),
+        }
+    }
+}
+
+type CliResult<T> = Result<T, CliError>;
+
+/// Runs the program with the arguments of the process and exits.
+pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("render") => render(&args[1..]),
+        Some("batch") => render_batch(&args[1..]),
+        Some("tile") => tile(&args[1..]),
+        Some("validate") => validate(&args[1..]),
+        Some("-h") | Some("--help") => {
+            print!("{}", USAGE);
+            Ok(())
+        },
+        Some(command) => Err(CliError::Usage(format!("unknown command `{}`", command))),
+        None => Err(CliError::Usage("missing command".to_owned())),
+    };
+    if let Err(e) = result {
+        eprintln!("colorful-map: {}", e);
+        process::exit(match e {
+            CliError::Usage(..) => 2,
+            _ => 1,
+        });
+    }
+}
+
+/// The output formats of the render command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Svg,
+    #[cfg(feature = "raster")]
+    Png,
+    GeoJson,
+}
+
+impl Format {
+    /// The file extension used by `batch`.
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            #[cfg(feature = "raster")]
+            Format::Png => "png",
+            Format::GeoJson => "geojson",
+        }
+    }
+}
+
+/// Options and arguments of the commands.
+#[derive(Debug)]
+struct Args {
+    input: String,
+    output: String,
+    address: Option<TileAddress>,
+    stylesheet: Option<String>,
+    embed_stylesheet: bool,
+    format: Format,
+    gl_style: Option<Arc<GlStyle>>,
+    gl_style_output: StyleOutput,
+    size: u32,
+    jobs: usize,
+    min_zoom: u8,
+    max_zoom: u8,
+    layer: Option<String>,
+    options: Options,
+    // The options given on the command line, to reject those that do
+    // not apply to a command.
+    given: Vec<String>,
+}
+
+impl Args {
+    fn stylesheet(&self) -> Stylesheet<'_> {
+        match self.stylesheet {
+            Some(ref url) => Stylesheet::Linked(url),
+            None if self.embed_stylesheet => Stylesheet::Embedded(document::STYLESHEET),
+            None => Stylesheet::None,
+        }
+    }
+}
+
+/// Parses the options of all commands and returns the positional arguments.
+fn parse_args(args: &[String]) -> CliResult<(Args, Vec<String>)> {
+    let mut parsed = Args {
+        input: "-".to_owned(),
+        output: "-".to_owned(),
+        address: None,
+        stylesheet: None,
+        embed_stylesheet: true,
+        format: Format::Svg,
+        gl_style: None,
+        gl_style_output: StyleOutput::Inline,
+        size: 256,
+        jobs: batch::default_threads(),
+        min_zoom: 0,
+        max_zoom: 14,
+        layer: None,
+        options: Options::default(),
+        given: Vec::new(),
+    };
+    let mut positional = Vec::new();
+    let (mut z, mut x, mut y) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with('-') && arg != "-" {
+            parsed.given.push(arg.clone());
+        }
+        let mut value = || args.next().cloned()
+            .ok_or_else(|| CliError::Usage(format!("missing value for `{}`", arg)));
+        match arg.as_str() {
+            "-o" | "--output" => parsed.output = value()?,
+            "--z" => z = Some(parse_number(&value()?, arg)?),
+            "--x" => x = Some(parse_number(&value()?, arg)?),
+            "--y" => y = Some(parse_number(&value()?, arg)?),
+            "--stylesheet" => parsed.stylesheet = Some(value()?),
+            "--no-stylesheet" => parsed.embed_stylesheet = false,
+            "--gl-style" => parsed.gl_style = Some(Arc::new(read_gl_style(&value()?)?)),
+            "--gl-style-classes" => parsed.gl_style_output = StyleOutput::Classes,
+            "--format" => parsed.format = match value()?.as_str() {
+                "svg" => Format::Svg,
+                #[cfg(feature = "raster")]
+                "png" => Format::Png,
+                #[cfg(not(feature = "raster"))]
+                "png" => return Err(CliError::Usage(
+                    "PNG support was disabled at compile time".to_owned())),
+                "geojson" => Format::GeoJson,
+                other => return Err(CliError::Usage(format!("unknown format `{}`", other))),
+            },
+            "--size" => parsed.size = match parse_number(&value()?, arg)? {
+                size @ 256 | size @ 512 => size,
+                _ => return Err(CliError::Usage("`--size` must be 256 or 512".to_owned())),
+            },
+            "-j" | "--jobs" => parsed.jobs = parse_number(&value()?, arg)? as usize,
+            "--lenient" => {
+                parsed.options.lenient = true;
+                parsed.options.skip_unknown_versions = true;
+            },
+            "--skip-unknown-versions" => parsed.options.skip_unknown_versions = true,
+            "--no-decompress" => parsed.options.decompress = false,
+            "--min-zoom" => parsed.min_zoom = parse_zoom(&value()?, arg)?,
+            "--max-zoom" => parsed.max_zoom = parse_zoom(&value()?, arg)?,
+            "--layer" => parsed.layer = Some(value()?),
+            "--id-tag" => parsed.options.id_tag = Some(value()?),
+            "--duplicate-ids" => parsed.options.report_duplicate_ids = true,
+            "--filter" => parsed.options.filter = Some(Filter::parse(&value()?).map_err(
+                |e| CliError::Usage(format!("invalid value for `{}`: {}", arg, e)))?),
+            "--classes" => parsed.options.classes = read_class_mapping(&value()?)?,
+            "--data-tags" => parsed.options.data_tags = match value()?.as_str() {
+                "*" => DataTags::All,
+                keys => DataTags::Only(parse_names(keys)),
+            },
+            "--only-layers" => parsed.options.include_layers = Some(parse_names(&value()?)),
+            "--exclude-layers" => parsed.options.exclude_layers = parse_names(&value()?),
+            "--layer-order" => parsed.options.layer_order = Some(parse_names(&value()?)),
+            "--sort-rank" => {
+                let value = value()?;
+                let (layer, rank) = value.split_once('=').and_then(
+                    |(layer, rank)| Some((layer, rank.parse::<u16>().ok()?))).ok_or_else(
+                    || CliError::Usage(format!("invalid value `{}` for `{}`", value, arg)))?;
+                parsed.options.default_sort_ranks.insert(layer.to_owned(), rank);
+            },
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            },
+            "-" => positional.push(arg.clone()),
+            _ if arg.starts_with('-') =>
+                return Err(CliError::Usage(format!("unknown option `{}`", arg))),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    parsed.address = match (z, x, y) {
+        (None, None, None) => None,
+        (Some(z), Some(x), Some(y)) => {
+            let address = if z < 32 { TileAddress::new(z as u8, x, y) } else { None };
+            Some(address.ok_or_else(
+                || CliError::Usage(format!("there is no tile {}/{}/{}", z, x, y)))?)
+        },
+        _ => return Err(CliError::Usage("--z, --x and --y must be used together".to_owned())),
+    };
+    Ok((parsed, positional))
+}
+
+/// Options of the commands that read tiles.
+const READ_OPTIONS: &[&str] = &["--no-decompress"];
+/// Options of the commands that read a single tile.
+const ADDRESS_OPTIONS: &[&str] = &["--z", "--x", "--y"];
+/// Options of the commands that render tiles.
+const RENDER_OPTIONS: &[&str] = &[
+    "--format", "--size", "--stylesheet", "--no-stylesheet", "--gl-style", "--gl-style-classes",
+    "--lenient", "--skip-unknown-versions", "--id-tag", "--duplicate-ids", "--filter",
+    "--classes", "--data-tags", "--only-layers", "--exclude-layers", "--layer-order",
+    "--sort-rank",
+];
+
+/// Fails if an option was given that is not in one of the accepted
+/// groups, it would not do anything.
+fn accept_options(args: &Args, command: &str, accepted: &[&[&str]]) -> CliResult<()> {
+    let accepted = |option: &str| accepted.iter().any(|group| group.contains(&option));
+    match args.given.iter().find(|given| !accepted(given)) {
+        Some(option) => Err(CliError::Usage(
+            format!("`{}` can not be used with `{}`", option, command))),
+        None => Ok(()),
+    }
+}
+
+fn parse_render_args(args: &[String]) -> CliResult<Args> {
+    let (mut parsed, positional) = parse_args(args)?;
+    match positional.len() {
+        0 => (),
+        1 => parsed.input = positional[0].clone(),
+        _ => return Err(CliError::Usage(format!("unexpected argument `{}`", positional[1]))),
+    }
+    Ok(parsed)
+}
+
+/// Splits a comma separated list of layer names or keys.
+fn parse_names(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_owned).collect()
+}
+
+fn parse_number(value: &str, option: &str) -> CliResult<u32> {
+    value.parse().map_err(|_| CliError::Usage(
+        format!("invalid value `{}` for `{}`", value, option)))
+}
+
+fn parse_zoom(value: &str, option: &str) -> CliResult<u8> {
+    match parse_number(value, option)? {
+        z if z < 32 => Ok(z as u8),
+        _ => Err(CliError::Usage(format!("`{}` must be below 32", option))),
+    }
+}
+
+/// Reads a text file like a style or class mapping.
+fn read_text(path: &str) -> CliResult<String> {
+    String::from_utf8(read_input(path)?).map_err(
+        |_| CliError::Io(path.to_owned(), io::Error::new(io::ErrorKind::InvalidData, "not UTF-8")))
+}
+
+/// Reads a GL style file.
+fn read_gl_style(path: &str) -> CliResult<GlStyle> {
+    let (style, warnings) = GlStyle::from_json(&read_text(path)?)
+        .map_err(|e| CliError::Tile(path.to_owned(), e))?;
+    print_warnings(path, warnings);
+    Ok(style)
+}
+
+/// Reads a class mapping from a JSON or TOML file.
+fn read_class_mapping(path: &str) -> CliResult<ClassMapping> {
+    let text = read_text(path)?;
+    let mapping = if path.ends_with(".toml") {
+        #[cfg(feature = "toml")]
+        { ClassMapping::from_toml(&text) }
+        #[cfg(not(feature = "toml"))]
+        return Err(CliError::Usage("TOML support was disabled at compile time".to_owned()));
+    } else {
+        ClassMapping::from_json(&text)
+    };
+    mapping.map_err(|e| CliError::Tile(path.to_owned(), e))
+}
+
+fn render(args: &[String]) -> CliResult<()> {
+    let args = parse_render_args(args)?;
+    accept_options(&args, "render", &[READ_OPTIONS, ADDRESS_OPTIONS, RENDER_OPTIONS,
+                                      &["-o", "--output"]])?;
+    if args.gl_style.is_some() && args.address.is_none() && args.format != Format::GeoJson {
+        return Err(CliError::Usage(
+            "--z, --x and --y are required for the zoom level of `--gl-style`".to_owned()));
+    }
+    let input = read_tile(&args)?;
+    let output = render_tile(&input, &args, &args.input, args.address)
+        .map_err(|e| CliError::Tile(args.input.clone(), e))?;
+    write_output(&args.output, &output)
+}
+
+/// Renders a tile in the requested format.
+fn render_tile(input: &[u8], args: &Args, name: &str, address: Option<TileAddress>)
+        -> Result<Vec<u8>, Error> {
+    match args.format {
+        Format::Svg => render_svg(input, args, name, address).map(String::into_bytes),
+        #[cfg(feature = "raster")]
+        Format::Png => {
+            let mut painter = match (&args.gl_style, address) {
+                (Some(style), Some(address)) => {
+                    let (style, zoom) = (style.clone(), f64::from(address.z));
+                    RasterPainter::with_styles(args.size, move |f| style.raster_styles(f, zoom))?
+                },
+                _ => RasterPainter::new(args.size)?,
+            };
+            let warnings = paint(input, &args.options, &mut painter)?;
+            print_warnings(name, warnings);
+            Ok(painter.into_png())
+        },
+        Format::GeoJson => {
+            let coordinates = address.map_or(Coordinates::TileLocal, Coordinates::Wgs84);
+            geojson::process_with_options(input, coordinates, &args.options)
+                .map(String::into_bytes)
+        },
+    }
+}
+
+/// Reads the input of `render` and `validate` from a file or an archive.
+fn read_tile(args: &Args) -> CliResult<Vec<u8>> {
+    if !is_archive(&args.input) {
+        return read_input(&args.input);
+    }
+    let address = args.address.ok_or_else(
+        || CliError::Usage("--z, --x and --y are required for archives".to_owned()))?;
+    read_archive(&mut None, &args.input, address)
+}
+
+/// Renders a tile as SVG document and prints warnings.
+fn render_svg(input: &[u8], args: &Args, name: &str, address: Option<TileAddress>)
+        -> Result<String, Error> {
+    let (style, zoom) = match (&args.gl_style, address) {
+        (Some(style), Some(address)) => (style, f64::from(address.z)),
+        _ => {
+            let rendered = process_with_options(input, &args.options)?;
+            print_warnings(name, rendered.warnings);
+            return Ok(document::document(&rendered.svg, args.stylesheet(), address));
+        },
+    };
+    let mut painter = SvgPainter::with_gl_style(style.clone(), zoom, args.gl_style_output)
+        .with_data_tags(args.options.data_tags.clone());
+    let warnings = paint(input, &args.options, &mut painter)?;
+    print_warnings(name, warnings);
+    // The default stylesheet would fight with the GL style.
+    let css = style.stylesheet(zoom);
+    let stylesheet = match args.stylesheet() {
+        Stylesheet::Embedded(_) if args.gl_style_output == StyleOutput::Classes =>
+            Stylesheet::Embedded(&css),
+        Stylesheet::Embedded(_) => Stylesheet::None,
+        stylesheet => stylesheet,
+    };
+    Ok(document::document(&String::from(painter), stylesheet, address))
+}
+
+fn print_warnings(name: &str, warnings: Vec<Error>) {
+    for warning in warnings {
+        eprintln!("colorful-map: {}: warning: {}", name, warning);
+    }
+}
+
+fn render_batch(args: &[String]) -> CliResult<()> {
+    let (args, positional) = parse_args(args)?;
+    if positional.len() != 2 {
+        return Err(CliError::Usage("expected an input and an output directory".to_owned()));
+    }
+    // Each tile has its own address and output file.
+    accept_options(&args, "batch", &[READ_OPTIONS, RENDER_OPTIONS, &["-j", "--jobs"]])?;
+    let (input, output_dir) = (&positional[0], Path::new(&positional[1]));
+    // Tiles in a directory tree have a path, tiles in an archive do not.
+    let mut errors = Vec::new();
+    let tiles: Vec<(TileAddress, Option<PathBuf>)> = if is_archive(input) {
+        list_archive(input)?.into_iter().map(|address| (address, None)).collect()
+    } else {
+        let found = batch::find_tiles(Path::new(input))
+            .map_err(|e| CliError::Io(input.clone(), e))?;
+        errors.extend(found.errors.into_iter()
+            .map(|(path, e)| CliError::Io(path.display().to_string(), e)));
+        found.tiles.into_iter().map(|(address, path)| (address, Some(path))).collect()
+    };
+    let results = batch::run_parallel(&tiles, args.jobs, || None, |archive, &(address, ref path)| {
+        let (name, data) = match *path {
+            Some(ref path) => {
+                let name = path.display().to_string();
+                let data = fs::read(path).map_err(|e| CliError::Io(name.clone(), e))?;
+                (name, data)
+            },
+            None => {
+                let name = format!("{}:{}/{}/{}", input, address.z, address.x, address.y);
+                (name, read_archive(archive, input, address)?)
+            },
+        };
+        let rendered = render_tile(&data, &args, &name, Some(address))
+            .map_err(|e| CliError::Tile(name, e))?;
+        let output = batch::tile_path(output_dir, address, args.format.extension());
+        let output_name = output.display().to_string();
+        fs::create_dir_all(output.parent().expect("tile paths have a parent"))
+            .and_then(|()| fs::write(&output, &rendered))
+            .map_err(|e| CliError::Io(output_name, e))
+    });
+    let total = tiles.len() + errors.len();
+    errors.extend(results.into_iter().filter_map(Result::err));
+    for e in &errors {
+        eprintln!("colorful-map: {}", e);
+    }
+    if !errors.is_empty() {
+        return Err(CliError::Batch(errors.len(), total));
+    }
+    Ok(())
+}
+
+fn validate(args: &[String]) -> CliResult<()> {
+    let args = parse_render_args(args)?;
+    accept_options(&args, "validate", &[READ_OPTIONS, ADDRESS_OPTIONS])?;
+    let input = read_tile(&args)?;
+    let violations = validate::validate_bytes_with_options(&input, &args.options)
+        .map_err(|e| CliError::Tile(args.input.clone(), e))?;
+    for violation in &violations {
+        println!("{}: {}", args.input, violation);
+    }
+    match violations.iter().filter(|v| v.severity == Severity::Error).count() {
+        0 => Ok(()),
+        errors => Err(CliError::Invalid(args.input.clone(), errors)),
+    }
+}
+
+fn tile(args: &[String]) -> CliResult<()> {
+    let (args, positional) = parse_args(args)?;
+    if positional.len() != 2 {
+        return Err(CliError::Usage("expected a GeoJSON file and an output".to_owned()));
+    }
+    accept_options(&args, "tile", &[&["--min-zoom", "--max-zoom", "--layer"]])?;
+    if args.min_zoom > args.max_zoom {
+        return Err(CliError::Usage("--min-zoom must not be above --max-zoom".to_owned()));
+    }
+    let (input, output) = (&positional[0], &positional[1]);
+    let geojson = String::from_utf8(read_input(input)?)
+        .map_err(|e| CliError::Io(input.clone(), io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    let layer = args.layer.clone().unwrap_or_else(|| {
+        Path::new(input).file_stem().and_then(|s| s.to_str())
+            .filter(|&s| s != "-").unwrap_or("geojson").to_owned()
+    });
+    let options = TileOptions {
+        layer,
+        min_zoom: args.min_zoom,
+        max_zoom: args.max_zoom,
+        ..TileOptions::default()
+    };
+    let tiled = tiler::tile_geojson(&geojson, &options)
+        .map_err(|e| CliError::Tile(input.clone(), e))?;
+    print_warnings(input, tiled.warnings);
+    let tiles = tiled.tiles;
+    if output.ends_with(".mbtiles") {
+        return write_mbtiles(output, &tiles, &options);
+    }
+    for &(address, ref data) in &tiles {
+        let path = batch::tile_path(Path::new(output), address, "mvt");
+        let name = path.display().to_string();
+        fs::create_dir_all(path.parent().expect("tile paths have a parent"))
+            .map_err(|e| CliError::Io(name.clone(), e))?;
+        write_output(&name, data)?;
+    }
+    Ok(())
+}
+
+/// Writes tiles and the metadata required by the MBTiles specification.
+#[cfg(feature = "mbtiles")]
+fn write_mbtiles(path: &str, tiles: &[(TileAddress, Vec<u8>)], options: &TileOptions)
+        -> CliResult<()> {
+    let tile_error = |e| CliError::Tile(path.to_owned(), e);
+    let mut archive = MbTiles::create(path).map_err(tile_error)?;
+    let layer = serde_json::Value::String(options.layer.clone());
+    let metadata = [
+        ("name", options.layer.clone()),
+        ("format", "pbf".to_owned()),
+        ("minzoom", options.min_zoom.to_string()),
+        ("maxzoom", options.max_zoom.to_string()),
+        ("json", format!("{{\"vector_layers\":[{{\"id\":{},\"fields\":{{}}}}]}}", layer)),
+    ];
+    for &(name, ref value) in &metadata {
+        archive.set_metadata(name, value).map_err(tile_error)?;
+    }
+    archive.insert_tiles(tiles).map_err(tile_error)
+}
+
+#[cfg(not(feature = "mbtiles"))]
+fn write_mbtiles(_: &str, _: &[(TileAddress, Vec<u8>)], _: &TileOptions) -> CliResult<()> {
+    Err(CliError::Usage("MBTiles support was disabled at compile time".to_owned()))
+}
+
+/// A tile archive given as input.
+enum Archive {
+    #[cfg(feature = "mbtiles")]
+    MbTiles(MbTiles),
+    PmTiles(PmTiles<File>),
+}
+
+impl Archive {
+    /// Opens the archive, the format is chosen by the file extension.
+    fn open(path: &str) -> CliResult<Archive> {
+        let tile_error = |e| CliError::Tile(path.to_owned(), e);
+        if path.ends_with(".pmtiles") {
+            return PmTiles::open(path).map(Archive::PmTiles).map_err(tile_error);
+        }
+        #[cfg(feature = "mbtiles")]
+        return MbTiles::open(path).map(Archive::MbTiles).map_err(tile_error);
+        #[cfg(not(feature = "mbtiles"))]
+        Err(CliError::Usage("MBTiles support was disabled at compile time".to_owned()))
+    }
+
+    fn addresses(&mut self) -> Result<Vec<TileAddress>, Error> {
+        match *self {
+            #[cfg(feature = "mbtiles")]
+            Archive::MbTiles(ref archive) => archive.addresses(),
+            Archive::PmTiles(ref mut archive) => archive.addresses(),
+        }
+    }
+
+    fn get(&mut self, address: TileAddress) -> Result<Option<Vec<u8>>, Error> {
+        match *self {
+            #[cfg(feature = "mbtiles")]
+            Archive::MbTiles(ref archive) => archive.get(address),
+            Archive::PmTiles(ref mut archive) => archive.get(address),
+        }
+    }
+}
+
+/// Returns true if the path refers to an MBTiles or PMTiles archive.
+fn is_archive(path: &str) -> bool {
+    path.ends_with(".mbtiles") || path.ends_with(".pmtiles")
+}
+
+/// Lists the tiles in an archive.
+fn list_archive(path: &str) -> CliResult<Vec<TileAddress>> {
+    Archive::open(path)?.addresses().map_err(|e| CliError::Tile(path.to_owned(), e))
+}
+
+/// Reads a tile from an archive.
+///
+/// The archive is opened on first use and kept open in `archive`.
+fn read_archive(archive: &mut Option<Archive>, path: &str, address: TileAddress)
+        -> CliResult<Vec<u8>> {
+    if archive.is_none() {
+        *archive = Some(Archive::open(path)?);
+    }
+    let archive = archive.as_mut().expect("archive was opened");
+    archive.get(address).map_err(|e| CliError::Tile(path.to_owned(), e))?
+        .ok_or_else(|| CliError::NoTile(path.to_owned(), address))
+}
+
+/// Reads a file or standard input if the path is `-`.
+fn read_input(path: &str) -> CliResult<Vec<u8>> {
+    let mut data = Vec::new();
+    let result = if path == "-" {
+        io::stdin().read_to_end(&mut data)
+    } else {
+        File::open(path).and_then(|mut f| f.read_to_end(&mut data))
+    };
+    result.map(|_| data).map_err(|e| CliError::Io(path.to_owned(), e))
+}
+
+/// Writes a file or standard output if the path is `-`.
+fn write_output(path: &str, data: &[u8]) -> CliResult<()> {
+    let result = if path == "-" {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(data).and_then(|_| stdout.flush())
+    } else {
+        File::create(path).and_then(|mut f| f.write_all(data))
+    };
+    result.map_err(|e| CliError::Io(path.to_owned(), e))
+}
+
+#[test]
+fn test_parse_render_args() {
+    let args: Vec<String> = ["tile.mvt", "-o", "tile.svg", "--z", "12", "--x", "2138", "--y", "1388"]
+        .iter().map(|s| s.to_string()).collect();
+    let parsed = parse_render_args(&args).unwrap();
+    assert_eq!(parsed.input, "tile.mvt");
+    assert_eq!(parsed.output, "tile.svg");
+    assert_eq!(parsed.address, TileAddress::new(12, 2138, 1388));
+    assert_eq!(parsed.format, Format::Svg);
+    assert_eq!(parsed.size, 256);
+
+    let parsed = parse_render_args(&[]).unwrap();
+    assert_eq!((parsed.input.as_str(), parsed.output.as_str()), ("-", "-"));
+    assert!(parsed.address.is_none());
+
+    let invalid: Vec<String> = vec!["--z".to_owned(), "1".to_owned(), "--x".to_owned(), "2".to_owned()];
+    assert!(parse_render_args(&invalid).is_err());
+    let invalid: Vec<String> = ["--z", "1", "--x", "2", "--y", "0"].iter().map(|s| s.to_string()).collect();
+    assert!(parse_render_args(&invalid).is_err());
+    let invalid: Vec<String> = ["--format", "png", "--size", "300"].iter().map(|s| s.to_string()).collect();
+    assert!(parse_render_args(&invalid).is_err());
+
+    let args: Vec<String> = ["--lenient", "--only-layers", "roads,water", "--sort-rank", "roads=10"]
+        .iter().map(|s| s.to_string()).collect();
+    let options = parse_render_args(&args).unwrap().options;
+    assert!(options.lenient && options.decompress);
+    assert_eq!(options.include_layers, Some(vec!["roads".to_owned(), "water".to_owned()]));
+    assert_eq!(options.default_sort_ranks.get("roads"), Some(&10));
+}
+
+#[test]
+fn test_reject_options() {
+    let rejected = |command: fn(&[String]) -> CliResult<()>, args: &[&str], option: &str| {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        match command(&args) {
+            Err(CliError::Usage(ref message)) => assert!(message.contains(option), "{}", message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    };
+    rejected(render_batch, &["tiles/", "svg/", "-o", "tile.svg"], "`-o`");
+    rejected(render, &["tile.mvt", "--min-zoom", "3"], "`--min-zoom`");
+    rejected(validate, &["tile.mvt", "--no-decompress", "-o", "out.txt"], "`-o`");
+    rejected(tile, &["in.geojson", "tiles/", "--max-zoom", "3", "--filter", "kind == path"], "`--filter`");
+}
@@ -0,0 +1,92 @@
+//! Detects and decompresses compressed tiles.
+//!
+//! Tile servers and archives often deliver gzip or zlib compressed
+//! tiles. Both formats start with magic bytes that an uncompressed
+//! tile never starts with, so they can be recognized reliably.
+
+use std::borrow::Cow;
+use std::io::Read;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use error::{Error, Result};
+
+/// The largest size of decompressed data.
+///
+/// Tiles are rarely larger than a few megabytes, the limit stops small
+/// files that inflate to gigabytes from exhausting the memory.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A compression format of tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Gzip (RFC 1952).
+    Gzip,
+    /// Zlib (RFC 1950).
+    Zlib,
+}
+
+/// Detects the compression format from the first bytes of the data.
+///
+/// Returns `None` for uncompressed data.
+pub fn sniff(data: &[u8]) -> Option<Format> {
+    match *data {
+        [0x1f, 0x8b, ..] => Some(Format::Gzip),
+        // Deflate compression with a valid header checksum.
+        [cmf, flg, ..] if cmf & 0x0f == 8 && cmf >> 4 <= 7
+            && (cmf as u16 * 256 + flg as u16).is_multiple_of(31) => Some(Format::Zlib),
+        _ => None,
+    }
+}
+
+/// Decompresses gzip or zlib compressed data.
+///
+/// Uncompressed data is returned unchanged. Fails if the data inflates
+/// to more than `MAX_DECOMPRESSED_SIZE` bytes.
+pub fn decompress(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    decompress_limited(data, MAX_DECOMPRESSED_SIZE)
+}
+
+fn decompress_limited(data: &[u8], limit: u64) -> Result<Cow<'_, [u8]>> {
+    let mut decompressed = Vec::new();
+    // Read one byte more than allowed to tell a full buffer from a cut one.
+    let result = match sniff(data) {
+        Some(Format::Gzip) => GzDecoder::new(data).take(limit + 1).read_to_end(&mut decompressed),
+        Some(Format::Zlib) => ZlibDecoder::new(data).take(limit + 1).read_to_end(&mut decompressed),
+        None => return Ok(Cow::Borrowed(data)),
+    };
+    result.map_err(Error::Decompression)?;
+    if decompressed.len() as u64 > limit {
+        return Err(Error::DecompressedSize(limit));
+    }
+    Ok(Cow::Owned(decompressed))
+}
+
+#[test]
+fn test_decompress() {
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    let gzip: &[u8] = include_bytes!("../web/tile.mvt");
+    assert_eq!(sniff(gzip), Some(Format::Gzip));
+    let tile = decompress(gzip).unwrap().into_owned();
+    assert_eq!(sniff(&tile), None);
+    assert_eq!(decompress(&tile).unwrap(), Cow::Borrowed(&tile[..]));
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), ::flate2::Compression::default());
+    encoder.write_all(&tile).unwrap();
+    let zlib = encoder.finish().unwrap();
+    assert_eq!(sniff(&zlib), Some(Format::Zlib));
+    assert_eq!(decompress(&zlib).unwrap().into_owned(), tile);
+
+    match decompress(&gzip[..100]) {
+        Err(Error::Decompression(_)) => (),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    let size = tile.len() as u64;
+    assert_eq!(decompress_limited(gzip, size).unwrap().len() as u64, size);
+    match decompress_limited(gzip, size - 1) {
+        Err(Error::DecompressedSize(limit)) if limit == size - 1 => (),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
@@ -1,6 +1,6 @@
 //! The cursor interprets a geometry and returns a chain of commands.
 
-use protobuf::{ProtobufError, ProtobufResult};
+use error::{Error, Result};
 
 /// Chains of commands form paths and polygons.
 ///
@@ -29,14 +29,19 @@ impl<'a> Cursor<'a> {
     /// Get a cursor for the geometry.
     pub fn new(geometry: &'a[u32], scale: f32) -> Cursor<'a> {
         Cursor {
-            geometry: geometry,
+            geometry,
             id: 0,
             count: 0,
             x: 0,
             y: 0,
-            scale: scale,
+            scale,
         }
     }
+
+    /// The unscaled position after the last command.
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
 }
 
 /// Decode a 32bit integer according to the protobuf zigzag rules.
@@ -46,10 +51,17 @@ pub fn de_zigzag(n: u32) -> i32 {
     ((n >> 1) as i32) ^ (-((n & 1) as i32))
 }
 
+/// Encode a 32bit integer according to the protobuf zigzag rules.
+///
+/// The inverse of `de_zigzag`.
+pub fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
 impl<'a> Iterator for Cursor<'a> {
-    type Item = ProtobufResult<Command>;
+    type Item = Result<Command>;
 
-    fn next(&mut self) -> Option<ProtobufResult<Command>> {
+    fn next(&mut self) -> Option<Result<Command>> {
         use self::Command::*;
         if self.geometry.is_empty() {
             return None;
@@ -59,18 +71,30 @@ impl<'a> Iterator for Cursor<'a> {
             self.geometry = geometry;
             self.id = command & 0x7;
             self.count = command >> 3;
+            if self.count == 0 {
+                self.geometry = Default::default();
+                return Some(Err(Error::ZeroCount { command: self.id }));
+            }
         }
         self.count -= 1;
         match self.id {
             1 | 2 if self.geometry.len() < 2 => {
                 self.geometry = Default::default();
-                Some(Err(ProtobufError::WireError(
-                    format!("mvt: Expected at least two remaining integers in geometry.")
-                )))
+                Some(Err(Error::TruncatedGeometry { command: self.id }))
             }
             1 | 2 => {
-                self.x += de_zigzag(self.geometry[0]);
-                self.y += de_zigzag(self.geometry[1]);
+                let x = self.x.checked_add(de_zigzag(self.geometry[0]));
+                let y = self.y.checked_add(de_zigzag(self.geometry[1]));
+                match (x, y) {
+                    (Some(x), Some(y)) => {
+                        self.x = x;
+                        self.y = y;
+                    },
+                    _ => {
+                        self.geometry = Default::default();
+                        return Some(Err(Error::PositionOverflow));
+                    },
+                }
                 self.geometry = &self.geometry[2..];
                 let x = self.x as f32 * self.scale;
                 let y = self.y as f32 * self.scale;
@@ -84,10 +108,20 @@ impl<'a> Iterator for Cursor<'a> {
             7 => Some(Ok(ClosePath)),
             _ => {
                 self.geometry = Default::default();
-                Some(Err(ProtobufError::WireError(
-                    format!("mvt: command integer, expected 1, 2 or 7, found {}", self.id)
-                )))
+                Some(Err(Error::UnknownCommand(self.id)))
             }
         }
     }
 }
+
+#[test]
+fn test_invalid_geometry() {
+    let commands = |geometry: &[u32]| Cursor::new(geometry, 1.0).collect::<Vec<_>>();
+    let zero_count = commands(&[1, 2, 2, 9, 2, 2]);
+    assert_eq!(zero_count.len(), 1);
+    assert!(matches!(zero_count[0], Err(Error::ZeroCount { command: 1 })));
+    let overflow = commands(&[9, 0xffff_fffe, 0, 10, 0xffff_fffe, 0]);
+    assert_eq!(overflow.len(), 2);
+    assert!(matches!(overflow[1], Err(Error::PositionOverflow)));
+    assert!(commands(&[9, 2, 2, 10, 4, 4]).iter().all(Result::is_ok));
+}
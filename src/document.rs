@@ -0,0 +1,60 @@
+//! Wraps SVG fragments into standalone SVG documents.
+
+use std::fmt::Write;
+
+use escape::Escaped;
+use projection::TileAddress;
+
+/// The stylesheet of the web map.
+pub const STYLESHEET: &str = include_str!("../web/style.css");
+
+/// How a document refers to its stylesheet.
+#[derive(Debug, Clone, Copy)]
+pub enum Stylesheet<'a> {
+    /// Include the CSS in a style element.
+    Embedded(&'a str),
+    /// Reference the stylesheet by URL.
+    Linked(&'a str),
+    /// Do not style the document.
+    None,
+}
+
+/// Creates an SVG document for a tile rendered by `process`.
+///
+/// The document is 256 by 256 pixels like the tiles in the web map.
+/// If the address of the tile is known it is used as the id of the
+/// root element in the same format as the web map: `{z}-{x}-{y}`.
+pub fn document(fragment: &str, stylesheet: Stylesheet, address: Option<TileAddress>) -> String {
+    let mut s = String::with_capacity(fragment.len() + 256);
+    s.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    if let Stylesheet::Linked(url) = stylesheet {
+        writeln!(s, "<?xml-stylesheet type=\"text/css\" href=\"{}\"?>", Escaped(url)).unwrap();
+    }
+    s.push_str("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                width=\"256\" height=\"256\" viewBox=\"0 0 256 256\"");
+    if let Some(address) = address {
+        write!(s, " id=\"{}-{}-{}\"", address.z, address.x, address.y).unwrap();
+    }
+    s.push_str(">\n");
+    if let Stylesheet::Embedded(css) = stylesheet {
+        write!(s, "<style type=\"text/css\">\n{}</style>\n", Escaped(css)).unwrap();
+    }
+    s.push_str(fragment);
+    s.push_str("</svg>\n");
+    s
+}
+
+#[test]
+fn test_document() {
+    let fragment = "<path class=\"layer-roads kind-path min-zoom-0\" d=\"M 0 0 L 2 2 \"></path>\n";
+    assert_eq!(document(fragment, Stylesheet::Linked("style.css?a=1&b=2"),
+                        TileAddress::new(12, 2138, 1388)),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <?xml-stylesheet type=\"text/css\" href=\"style.css?a=1&amp;b=2\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"256\" height=\"256\" \
+         viewBox=\"0 0 256 256\" id=\"12-2138-1388\">\n\
+         <path class=\"layer-roads kind-path min-zoom-0\" d=\"M 0 0 L 2 2 \"></path>\n\
+         </svg>\n");
+    assert!(document("", Stylesheet::Embedded(STYLESHEET), None)
+        .contains("<style type=\"text/css\">\nbody {"));
+}
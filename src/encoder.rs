@@ -0,0 +1,335 @@
+//! Writes Mapbox Vector Tiles.
+//!
+//! The reverse direction of `process`: features with integer tile
+//! coordinates and tags are collected in layers and encoded as an
+//! MVT version 2 tile. Keys and values are deduplicated per layer and
+//! geometries are delta and zigzag encoded. Polygon rings keep the
+//! orientation of `geometry::Polygon`, which is the one the
+//! specification requires.
+
+use std::collections::HashMap;
+
+use protobuf::{Message, RepeatedField};
+
+use cursor::zigzag;
+use error::{Error, Result};
+use geometry::{Geometry, Part, Point};
+use tag::Value;
+use vector_tile::{Tile, Tile_Feature, Tile_GeomType, Tile_Layer, Tile_Value};
+
+/// Identifies equal values for deduplication.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum ValueKey {
+    String(String),
+    Float32(u32),
+    Float64(u64),
+    Int64(i64),
+    Uint64(u64),
+    Bool(bool),
+}
+
+/// Collects the features of a layer.
+#[derive(Debug)]
+pub struct LayerBuilder {
+    name: String,
+    extent: u32,
+    keys: Vec<String>,
+    values: Vec<Tile_Value>,
+    key_indices: HashMap<String, u32>,
+    value_indices: HashMap<ValueKey, u32>,
+    features: Vec<Tile_Feature>,
+}
+
+impl LayerBuilder {
+    /// Creates an empty layer.
+    ///
+    /// Coordinates of features range from 0 to `extent`,
+    /// 4096 is the usual extent.
+    pub fn new(name: &str, extent: u32) -> LayerBuilder {
+        LayerBuilder {
+            name: name.to_owned(),
+            extent,
+            keys: Vec::new(),
+            values: Vec::new(),
+            key_indices: HashMap::new(),
+            value_indices: HashMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    /// Returns the size of the tile in tile coordinates.
+    pub fn extent(&self) -> u32 {
+        self.extent
+    }
+
+    /// Returns true if no features were added.
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Adds a feature to the layer.
+    ///
+    /// Fails if the geometry is empty or a linestring has less than two
+    /// distinct points.
+    pub fn add_feature(&mut self, id: Option<u64>, geometry: &Geometry, tags: &[(&str, Value)])
+            -> Result<()> {
+        let mut feature = Tile_Feature::new();
+        let (geom_type, geometry) = encode_geometry(geometry)?;
+        feature.set_field_type(geom_type);
+        feature.set_geometry(geometry);
+        if let Some(id) = id {
+            feature.set_id(id);
+        }
+        let mut indices = Vec::with_capacity(tags.len() * 2);
+        for &(key, ref value) in tags {
+            indices.push(self.key_index(key));
+            indices.push(self.value_index(value));
+        }
+        feature.set_tags(indices);
+        self.features.push(feature);
+        Ok(())
+    }
+
+    fn key_index(&mut self, key: &str) -> u32 {
+        if let Some(&index) = self.key_indices.get(key) {
+            return index;
+        }
+        let index = self.keys.len() as u32;
+        self.keys.push(key.to_owned());
+        self.key_indices.insert(key.to_owned(), index);
+        index
+    }
+
+    fn value_index(&mut self, value: &Value) -> u32 {
+        let mut tile_value = Tile_Value::new();
+        let key = match *value {
+            Value::String(s) => {
+                tile_value.set_string_value(s.to_owned());
+                ValueKey::String(s.to_owned())
+            },
+            Value::Float32(x) => {
+                tile_value.set_float_value(x);
+                ValueKey::Float32(x.to_bits())
+            },
+            Value::Float64(x) => {
+                tile_value.set_double_value(x);
+                ValueKey::Float64(x.to_bits())
+            },
+            Value::Int64(x) => {
+                tile_value.set_sint_value(x);
+                ValueKey::Int64(x)
+            },
+            Value::Uint64(x) => {
+                tile_value.set_uint_value(x);
+                ValueKey::Uint64(x)
+            },
+            Value::Bool(x) => {
+                tile_value.set_bool_value(x);
+                ValueKey::Bool(x)
+            },
+        };
+        if let Some(&index) = self.value_indices.get(&key) {
+            return index;
+        }
+        let index = self.values.len() as u32;
+        self.values.push(tile_value);
+        self.value_indices.insert(key, index);
+        index
+    }
+
+    /// Returns the encoded layer.
+    pub fn build(self) -> Tile_Layer {
+        let mut layer = Tile_Layer::new();
+        layer.set_version(2);
+        layer.set_name(self.name);
+        layer.set_extent(self.extent);
+        layer.set_keys(RepeatedField::from_vec(self.keys));
+        layer.set_values(RepeatedField::from_vec(self.values));
+        layer.set_features(RepeatedField::from_vec(self.features));
+        layer
+    }
+}
+
+/// Collects the layers of a tile.
+#[derive(Debug, Default)]
+pub struct TileBuilder {
+    layers: Vec<Tile_Layer>,
+}
+
+impl TileBuilder {
+    /// Creates an empty tile.
+    pub fn new() -> TileBuilder {
+        TileBuilder::default()
+    }
+
+    /// Adds a layer to the tile.
+    ///
+    /// Layers are painted in the order they are added.
+    pub fn add_layer(&mut self, layer: LayerBuilder) {
+        self.layers.push(layer.build());
+    }
+
+    /// Returns the tile.
+    pub fn build(self) -> Tile {
+        let mut tile = Tile::new();
+        tile.set_layers(RepeatedField::from_vec(self.layers));
+        tile
+    }
+
+    /// Returns the encoded tile ready to be written to a file.
+    pub fn to_bytes(self) -> Result<Vec<u8>> {
+        Ok(self.build().write_to_bytes()?)
+    }
+}
+
+/// Writes commands and tracks the position of the cursor.
+struct GeometryWriter {
+    geometry: Vec<u32>,
+    x: i32,
+    y: i32,
+}
+
+impl GeometryWriter {
+    /// Fails if the count does not fit in the 29 bits of a command integer.
+    fn command(&mut self, id: u32, count: usize) -> Result<()> {
+        if count >= 1 << 29 {
+            return Err(Error::InvalidGeometry("a command can be repeated at most 2^29 - 1 times"));
+        }
+        self.geometry.push((id & 0x7) | ((count as u32) << 3));
+        Ok(())
+    }
+
+    fn points(&mut self, id: u32, points: &[Point]) -> Result<()> {
+        self.command(id, points.len())?;
+        for &(x, y) in points {
+            self.geometry.push(zigzag(x.wrapping_sub(self.x)));
+            self.geometry.push(zigzag(y.wrapping_sub(self.y)));
+            self.x = x;
+            self.y = y;
+        }
+        Ok(())
+    }
+
+    /// Writes a linestring or, if `close` is set, a polygon ring.
+    fn path(&mut self, points: &[Point], close: bool) -> Result<()> {
+        self.points(1, &points[..1])?;
+        self.points(2, &points[1..])?;
+        if close {
+            self.command(7, 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_geometry(geometry: &Geometry) -> Result<(Tile_GeomType, Vec<u32>)> {
+    let mut writer = GeometryWriter { geometry: Vec::new(), x: 0, y: 0 };
+    let mut points = Vec::new();
+    for part in geometry.parts() {
+        match part {
+            Part::Point(point) => points.push(point),
+            Part::LineString(line) => {
+                let line = without_repeated_points(line);
+                if line.len() < 2 {
+                    return Err(Error::InvalidGeometry("a linestring needs two distinct points"));
+                }
+                writer.path(&line, false)?;
+            },
+            Part::Polygon(polygon) => for ring in polygon.rings() {
+                // The ClosePath command returns to the first point.
+                let mut ring = without_repeated_points(ring);
+                if ring.len() > 1 && ring.first() == ring.last() {
+                    ring.pop();
+                }
+                if ring.len() < 3 {
+                    return Err(Error::InvalidGeometry("a ring needs three distinct points"));
+                }
+                writer.path(&ring, true)?;
+            },
+        }
+    }
+    // All points of a feature share one MoveTo command.
+    if !points.is_empty() {
+        writer.points(1, &points)?;
+    }
+    let geom_type = geometry.geom_type();
+    if writer.geometry.is_empty() {
+        return Err(Error::InvalidGeometry(match geom_type {
+            Tile_GeomType::POINT => "a point geometry needs a point",
+            Tile_GeomType::LINESTRING => "a linestring geometry needs a line",
+            _ => "a polygon geometry needs a ring",
+        }));
+    }
+    Ok((geom_type, writer.geometry))
+}
+
+fn without_repeated_points(points: &[Point]) -> Vec<Point> {
+    let mut points = points.to_vec();
+    points.dedup();
+    points
+}
+
+#[cfg(test)]
+use geometry::{MultiPolygon, Polygon};
+
+#[test]
+fn test_encode_geometry() {
+    // The examples of the specification, section 4.3.5.
+    assert_eq!(encode_geometry(&Geometry::Point((25, 17))).unwrap().1,
+               vec![9, 50, 34]);
+    assert_eq!(encode_geometry(&Geometry::MultiPoint(vec![(5, 7), (3, 2)])).unwrap().1,
+               vec![17, 10, 14, 3, 9]);
+    assert_eq!(encode_geometry(&Geometry::MultiLineString(vec![vec![(2, 2), (2, 10), (10, 10)],
+                                                               vec![(1, 1), (3, 5)]])).unwrap().1,
+               vec![9, 4, 4, 18, 0, 16, 16, 0, 9, 17, 17, 10, 4, 8]);
+    // The closing point is left to the ClosePath command.
+    let triangle = Polygon::new(vec![(3, 6), (8, 12), (20, 34), (3, 6)], Vec::new()).unwrap();
+    assert_eq!(encode_geometry(&Geometry::Polygon(triangle)).unwrap().1,
+               vec![9, 6, 12, 18, 10, 12, 24, 44, 15]);
+    let polygons = Geometry::MultiPolygon(MultiPolygon::new(vec![
+        Polygon::new(vec![(0, 0), (10, 0), (10, 10), (0, 10)], Vec::new()).unwrap(),
+        Polygon::new(vec![(11, 11), (20, 11), (20, 20), (11, 20)],
+                     vec![vec![(13, 13), (13, 17), (17, 17), (17, 13)]]).unwrap(),
+    ]));
+    assert_eq!(encode_geometry(&polygons).unwrap().1, vec![
+        9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15,
+        9, 22, 2, 26, 18, 0, 0, 18, 17, 0, 15,
+        9, 4, 13, 26, 0, 8, 8, 0, 0, 7, 15,
+    ]);
+    assert!(encode_geometry(&Geometry::LineString(vec![(1, 1), (1, 1)])).is_err());
+    assert!(encode_geometry(&Geometry::MultiPolygon(MultiPolygon::default())).is_err());
+    let mut writer = GeometryWriter { geometry: Vec::new(), x: 0, y: 0 };
+    assert!(writer.command(2, (1 << 29) - 1).is_ok());
+    assert!(writer.command(2, 1 << 29).is_err());
+    assert_eq!(writer.geometry, vec![0xffff_fffa]);
+}
+
+#[test]
+fn test_tile_builder() {
+    let mut roads = LayerBuilder::new("roads", 4096);
+    let tags = [("kind", Value::String("path")), ("min_zoom", Value::Float32(12.0))];
+    roads.add_feature(Some(1), &Geometry::LineString(vec![(0, 0), (4096, 4096)]), &tags).unwrap();
+    roads.add_feature(Some(2), &Geometry::LineString(vec![(0, 10), (20, 10)]), &tags).unwrap();
+    let mut water = LayerBuilder::new("water", 256);
+    let lake = Polygon::new(vec![(0, 0), (0, 10), (10, 10)], Vec::new()).unwrap();
+    water.add_feature(None, &Geometry::Polygon(lake), &[("kind", Value::String("lake"))]).unwrap();
+    let mut builder = TileBuilder::new();
+    builder.add_layer(roads);
+    builder.add_layer(water);
+    let bytes = builder.to_bytes().unwrap();
+
+    let tile: Tile = ::protobuf::parse_from_bytes(&bytes).unwrap();
+    let layers = tile.get_layers();
+    assert_eq!(layers.len(), 2);
+    assert_eq!(layers[0].get_version(), 2);
+    assert_eq!(layers[0].get_keys().len(), 2);
+    assert_eq!(layers[0].get_values().len(), 2);
+    assert_eq!(layers[0].get_features()[1].get_tags(), &[0, 0, 1, 1]);
+    assert_eq!(layers[0].get_features()[1].get_id(), 2);
+    assert_eq!(layers[1].get_extent(), 256);
+    assert_eq!(layers[1].get_features()[0].get_field_type(), Tile_GeomType::POLYGON);
+    assert!(!layers[1].get_features()[0].has_id());
+    assert_eq!(::process(bytes.as_slice()).unwrap(),
+        "<path class=\"layer-roads kind-path min-zoom-12\" data-id=\"1\" d=\"M 0 0 L 256 256 \"></path>\n\
+         <path class=\"layer-roads kind-path min-zoom-12\" data-id=\"2\" d=\"M 0 0.625 L 1.25 0.625 \"></path>\n\
+         <path class=\"layer-water kind-lake min-zoom-0\" d=\"M 0 0 L 10 10 L 0 10 Z \"></path>\n");
+}
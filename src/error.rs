@@ -0,0 +1,213 @@
+//! Errors that occur while decoding and rendering map tiles.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use protobuf::ProtobufError;
+#[cfg(feature = "mbtiles")]
+use rusqlite;
+use serde_json;
+#[cfg(feature = "toml")]
+use toml;
+
+use vector_tile::Tile_GeomType as GeomType;
+
+/// A specialized result type for map tile processing.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Describes why a tile could not be processed.
+///
+/// Some variants only exist with certain features, so new variants
+/// may be added at any time.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The data is not a valid protobuf message.
+    Protobuf(ProtobufError),
+    /// Compressed tile data could not be decompressed.
+    Decompression(io::Error),
+    /// Compressed tile data inflates to more than the given number of bytes.
+    DecompressedSize(u64),
+    /// Reading a tile, an archive or another input failed.
+    Io(io::Error),
+    /// A PMTiles archive is malformed.
+    InvalidPmTiles(&'static str),
+    /// Data is compressed with a method that is not supported.
+    UnsupportedCompression(&'static str),
+    /// An MBTiles archive could not be read.
+    #[cfg(feature = "mbtiles")]
+    Sqlite(rusqlite::Error),
+    /// An image can not have the given size in pixels.
+    #[cfg(feature = "raster")]
+    InvalidImageSize(u32),
+    /// A layer uses a version of the specification that is not supported.
+    UnsupportedVersion {
+        /// The name of the layer.
+        layer: String,
+        /// The version of the layer.
+        version: u32,
+    },
+    /// A layer has an extent of zero.
+    ZeroExtent(String),
+    /// A geometry contains a command integer other than MoveTo, LineTo or ClosePath.
+    UnknownCommand(u32),
+    /// A command integer has a count of zero.
+    ZeroCount {
+        /// The command id.
+        command: u32,
+    },
+    /// A position of a geometry does not fit in a 32 bit integer.
+    PositionOverflow,
+    /// A geometry ended before all parameters of a command were read.
+    TruncatedGeometry {
+        /// The command id that was being decoded.
+        command: u32,
+    },
+    /// A command that is not allowed in the geometry type.
+    ///
+    /// For example a ClosePath in a linestring.
+    UnexpectedCommand {
+        /// The offending command id.
+        command: u32,
+        /// The type of the geometry.
+        geom_type: GeomType,
+    },
+    /// The tag list of a feature contains an odd number of integers.
+    OddTagCount(usize),
+    /// A tag refers to a key that is not in the layer's dictionary.
+    TagKeyOutOfRange(u32),
+    /// A tag refers to a value that is not in the layer's dictionary.
+    TagValueOutOfRange(u32),
+    /// A value in the layer's dictionary contains no data.
+    EmptyValue,
+    /// The input is not valid JSON.
+    Json(serde_json::Error),
+    /// The input is JSON but not valid GeoJSON.
+    InvalidGeoJson(&'static str),
+    /// A GeoJSON feature id is not an unsigned integer and can not be
+    /// stored in a vector tile.
+    UnsupportedId(String),
+    /// A filter can not be read.
+    InvalidFilter(&'static str),
+    /// A map style can not be read.
+    InvalidStyle(&'static str),
+    /// A layer of a map style can not be used.
+    StyleLayer {
+        /// The id of the style layer.
+        id: String,
+        /// The error of the style layer.
+        error: Box<Error>,
+    },
+    /// A class mapping can not be read.
+    InvalidMapping(&'static str),
+    /// The input is not valid TOML.
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    /// A geometry cannot be encoded.
+    InvalidGeometry(&'static str),
+    /// A tag required for rendering is missing or has the wrong type.
+    MissingTag(String),
+    /// Two features of a layer have the same id.
+    DuplicateId(u64),
+    /// An error occured in a specific feature.
+    Feature {
+        /// The name of the layer.
+        layer: String,
+        /// The index of the feature within its layer.
+        index: usize,
+        /// The error of the feature.
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Adds the location of a feature to an error.
+    pub fn in_feature(self, layer: &str, index: usize) -> Error {
+        Error::Feature {
+            layer: layer.to_owned(),
+            index,
+            error: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Error::*;
+        match *self {
+            Protobuf(ref e) => write!(f, "protobuf: {}", e),
+            Decompression(ref e) => write!(f, "compressed tile data is corrupt: {}", e),
+            DecompressedSize(limit) =>
+                write!(f, "compressed tile data inflates to more than {} bytes", limit),
+            Io(ref e) => write!(f, "io: {}", e),
+            InvalidPmTiles(reason) => write!(f, "pmtiles: {}", reason),
+            UnsupportedCompression(method) => write!(f, "{} compression is not supported", method),
+            #[cfg(feature = "mbtiles")]
+            Sqlite(ref e) => write!(f, "mbtiles: {}", e),
+            #[cfg(feature = "raster")]
+            InvalidImageSize(size) => write!(f, "png: an image can not be {} pixels wide", size),
+            UnsupportedVersion { ref layer, version } =>
+                write!(f, "layer {:?}: mvt version {} is not supported", layer, version),
+            ZeroExtent(ref layer) => write!(f, "layer {:?}: mvt: the extent must not be zero", layer),
+            UnknownCommand(id) =>
+                write!(f, "mvt: command integer, expected 1, 2 or 7, found {}", id),
+            ZeroCount { command } => write!(f, "mvt: command {} has a count of zero", command),
+            PositionOverflow => f.write_str("mvt: a position does not fit in a 32 bit integer"),
+            TruncatedGeometry { command } =>
+                write!(f, "mvt: command {} expects two more integers in the geometry", command),
+            UnexpectedCommand { command, geom_type } =>
+                write!(f, "mvt: command {} is not allowed in a {:?} geometry", command, geom_type),
+            OddTagCount(len) =>
+                write!(f, "mvt: a tag list must be an even number of integers, found {}", len),
+            TagKeyOutOfRange(index) => write!(f, "mvt: there is no tag key {}", index),
+            TagValueOutOfRange(index) => write!(f, "mvt: there is no tag value {}", index),
+            EmptyValue => f.write_str("mvt: a value must contain data"),
+            Json(ref e) => write!(f, "json: {}", e),
+            InvalidGeoJson(reason) => write!(f, "geojson: {}", reason),
+            UnsupportedId(ref id) =>
+                write!(f, "geojson: the id {} is not an unsigned integer", id),
+            InvalidFilter(reason) => write!(f, "filter: {}", reason),
+            InvalidStyle(reason) => write!(f, "style: {}", reason),
+            StyleLayer { ref id, ref error } => write!(f, "style layer {:?}: {}", id, error),
+            InvalidMapping(reason) => write!(f, "classes: {}", reason),
+            #[cfg(feature = "toml")]
+            Toml(ref e) => write!(f, "toml: {}", e),
+            InvalidGeometry(reason) => write!(f, "mvt: {}", reason),
+            MissingTag(ref key) => write!(f, "the tag {} is required", key),
+            DuplicateId(id) => write!(f, "mvt: the id {} is used by another feature", id),
+            Feature { ref layer, index, ref error } =>
+                write!(f, "layer {:?}, feature {}: {}", layer, index, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Protobuf(ref e) => Some(e),
+            Error::Decompression(ref e) | Error::Io(ref e) => Some(e),
+            Error::Json(ref e) => Some(e),
+            #[cfg(feature = "toml")]
+            Error::Toml(ref e) => Some(e),
+            #[cfg(feature = "mbtiles")]
+            Error::Sqlite(ref e) => Some(e),
+            Error::Feature { ref error, .. } | Error::StyleLayer { ref error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ProtobufError> for Error {
+    fn from(e: ProtobufError) -> Error {
+        Error::Protobuf(e)
+    }
+}
+
+#[cfg(feature = "mbtiles")]
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Sqlite(e)
+    }
+}
@@ -0,0 +1,108 @@
+//! Escapes text taken from map tiles before it is written to SVG.
+//!
+//! Map tiles are untrusted input. Everything derived from tags or
+//! layer names must pass through one of the wrappers in this module
+//! or the output could contain arbitrary markup.
+
+use std::fmt;
+
+/// Escapes a string for use in XML text content and attribute values.
+///
+/// Quotes are escaped too, so the result is safe in both
+/// single and double quoted attributes.
+#[derive(Debug, Clone, Copy)]
+pub struct Escaped<'a>(pub &'a str);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut last = 0;
+        for (i, c) in self.0.char_indices() {
+            let replacement = match c {
+                '<' => "&lt;",
+                '>' => "&gt;",
+                '&' => "&amp;",
+                '"' => "&quot;",
+                '\'' => "&#39;",
+                // Control characters are not allowed in XML 1.0.
+                '\u{0}'..='\u{8}' | '\u{b}' | '\u{c}' | '\u{e}'..='\u{1f}' => "\u{fffd}",
+                _ => continue,
+            };
+            f.write_str(&self.0[last..i])?;
+            f.write_str(replacement)?;
+            last = i + c.len_utf8();
+        }
+        f.write_str(&self.0[last..])
+    }
+}
+
+/// Sanitizes a string so it can be used as part of a CSS class name.
+///
+/// Only ASCII letters, digits, `-` and `_` are kept; all other
+/// characters are replaced with `_`. The result never contains
+/// whitespace, so a single tag value cannot add multiple classes.
+#[derive(Debug, Clone, Copy)]
+pub struct ClassToken<'a>(pub &'a str);
+
+impl<'a> fmt::Display for ClassToken<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::fmt::Write;
+        for c in self.0.chars() {
+            f.write_char(match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Turns a tag key into the name of a `data-*` attribute.
+///
+/// Lowercase ASCII letters, digits, `-` and `_` are kept. All other
+/// characters are written as `.` followed by the hexadecimal UTF-8
+/// bytes, so `name:en` becomes `name.3aen`. Different keys never
+/// share a name and the name is valid in XML and HTML.
+#[derive(Debug, Clone, Copy)]
+pub struct DataName<'a>(pub &'a str);
+
+impl<'a> fmt::Display for DataName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::fmt::Write;
+        for c in self.0.chars() {
+            match c {
+                'a'..='z' | '0'..='9' | '-' | '_' => f.write_char(c)?,
+                _ => for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    write!(f, ".{:02x}", byte)?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_escaped() {
+    assert_eq!(Escaped("plain text").to_string(), "plain text");
+    assert_eq!(Escaped("<script>alert('x')</script>").to_string(),
+               "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;");
+    assert_eq!(Escaped("a\" onload=\"b & c").to_string(),
+               "a&quot; onload=&quot;b &amp; c");
+    assert_eq!(Escaped("Frankfurt am Main \u{0}").to_string(), "Frankfurt am Main \u{fffd}");
+}
+
+#[test]
+fn test_class_token() {
+    assert_eq!(ClassToken("major_road").to_string(), "major_road");
+    assert_eq!(ClassToken("x\" onmouseover=\"alert(1)").to_string(),
+               "x__onmouseover__alert_1_");
+    assert_eq!(ClassToken("Straße a").to_string(), "Stra_e_a");
+}
+
+#[test]
+fn test_data_name() {
+    assert_eq!(DataName("min_zoom").to_string(), "min_zoom");
+    assert_eq!(DataName("name:en").to_string(), "name.3aen");
+    assert_eq!(DataName("Name").to_string(), ".4eame");
+    assert_eq!(DataName("a.3a").to_string(), "a.2e3a");
+    assert_eq!(DataName("x\" onload=\"ß").to_string(), "x.22.20onload.3d.22.c3.9f");
+}
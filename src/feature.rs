@@ -3,120 +3,174 @@
 //! Features are the basic building block of maps and
 //! describe all visible and invisible objects.
 
-use protobuf::{ProtobufError, ProtobufResult};
-
 use cursor::Cursor;
+use error::{Error, Result};
+use geometry::{Geometry, InvalidRings};
+use painter::Painter;
 use tag::{TagMap, Value};
-use storage::Rank;
 use vector_tile::{Tile_Feature, Tile_GeomType as GeomType};
 
+/// The sort rank of features without a `sort_rank` tag.
+pub const DEFAULT_SORT_RANK: u16 = 500;
+
 /// A feature consists of a geometry and tagging.
 ///
 /// Additionally it contains information about layer, sort_rank and scale.
 #[derive(Debug)]
 pub struct Feature<'a, 'k, 'v> {
-    id: Option<i64>,
+    id: Option<u64>,
     geom_type: GeomType,
     tags: TagMap<'k, 'v>,
     geometry: &'a [u32],
     layer: &'a str,
+    version: u32,
     scale: f32,
     /// The rank this feature should be drawn at.
     pub sort_rank: u16,
+    /// The rank of the layer, drawn before `sort_rank`.
+    ///
+    /// All layers have the rank 0 unless their order is given in the
+    /// options, so only the sort rank decides.
+    pub layer_rank: u16,
 }
 
 impl<'a, 'k, 'v> Feature<'a, 'k, 'v> {
     /// Creates a new feature.
-    pub fn new(raw_feature: &'a Tile_Feature, tags: TagMap<'k, 'v>, layer: &'a str, scale: f32)
-            -> ProtobufResult<Feature<'a, 'k, 'v>> {
-        let id = (&tags).get("id").and_then(Value::i64);
+    ///
+    /// `version` is the version of the specification used by the layer.
+    pub fn new(raw_feature: &'a Tile_Feature, tags: TagMap<'k, 'v>, layer: &'a str,
+               version: u32, scale: f32) -> Result<Feature<'a, 'k, 'v>> {
+        let id = if raw_feature.has_id() { Some(raw_feature.get_id()) } else { None };
         // features without sort_rank are usally labels and
         // are displayed above all other content.
-        let sort_rank = (&tags).get("sort_rank").and_then(Value::u16).unwrap_or(500);
+        let sort_rank = tags.get("sort_rank").and_then(Value::u16).unwrap_or(DEFAULT_SORT_RANK);
         Ok(Feature {
-            id: id,
+            id,
             geom_type: raw_feature.get_field_type(),
-            tags: tags,
+            tags,
             geometry: raw_feature.get_geometry(),
-            layer: layer,
-            scale: scale,
-            sort_rank: sort_rank})
+            layer,
+            version,
+            scale,
+            sort_rank,
+            layer_rank: 0})
     }
 
-    /// Compute an SVG fragment for the feature.
-    pub fn paint(&mut self, rank: &mut Rank) -> ProtobufResult<()> {
-        use vector_tile::Tile_GeomType::*;
-        match self.geom_type {
-            // Points usually carry texts and need more work.
-            POINT => Ok(()),
-            LINESTRING => {
-                rank.push_str("<path");
-                self.paint_metadata(rank)?;
-                self.paint_description(rank, false)?;
-                rank.push_str("></path>\n");
-                Ok(())
-            },
-            // Note: multi-polygons have holes and are filled with the even-odd rule in SVG.
-            POLYGON => {
-                rank.push_str("<path");
-                self.paint_metadata(rank)?;
-                self.paint_description(rank, true)?;
-                rank.push_str("></path>\n");
-                Ok(())
-            },
-            // Ignore unknown features.
-            UNKNOWN => Ok(()),
-        }
+    /// Returns the id of the feature.
+    pub fn id(&self) -> Option<u64> {
+        self.id
     }
 
-    fn paint_metadata(&self, rank: &mut Rank)
-            -> ProtobufResult<()> {
-        // class="kind-{} (boundary)? min-zoom-{}" (data-id="{}")?
-        // FIXME: Malicious map tiles can do XSS.
-        if self.id == Some(7996457) {
-            println!("{:?}", self.tags);
-        }
-        rank.push_format(format_args!(" class=\"layer-{} kind-{}",
-            self.layer,
-            self.tags.get("kind").and_then(Value::str)
-                .ok_or_else(|| ProtobufError::WireError("kind is required".to_owned()))?));
-        if self.tags.get("boundary").map_or(false, Value::yes) {
-            rank.push_str(" boundary");
+    /// Changes the sort rank if the feature has no `sort_rank` tag.
+    pub fn use_default_sort_rank(&mut self, sort_rank: u16) {
+        if self.tags.get("sort_rank").and_then(Value::u16).is_none() {
+            self.sort_rank = sort_rank;
         }
-        if self.tags.get("is_tunnel").map_or(false, Value::yes) {
-            rank.push_str(" is_tunnel");
+    }
+
+    /// Returns the position of the feature in the drawing order.
+    ///
+    /// Features with a lower layer rank are drawn first, then those with a
+    /// lower sort rank.
+    pub fn draw_order(&self) -> u32 {
+        u32::from(self.layer_rank) << 16 | u32::from(self.sort_rank)
+    }
+
+    /// Uses the value of a tag as id if the feature has no id.
+    pub fn use_id_tag(&mut self, key: &str) {
+        if self.id.is_none() {
+            self.id = self.tags.get(key).and_then(Value::u64);
         }
-        if self.tags.get("is_bridge").map_or(false, Value::yes) {
-            rank.push_str(" is_bridge");
+    }
+
+    /// Decodes the geometry of the feature.
+    pub fn decode_geometry(&self, invalid: InvalidRings) -> Result<Geometry> {
+        Geometry::decode(self.geom_type, self.geometry, self.version, invalid)
+    }
+
+    /// Returns the name of the layer the feature belongs to.
+    pub fn layer(&self) -> &'a str {
+        self.layer
+    }
+
+    /// Returns the geometry type.
+    pub fn geom_type(&self) -> GeomType {
+        self.geom_type
+    }
+
+    /// Returns the tags.
+    pub fn tags(&self) -> &TagMap<'k, 'v> {
+        &self.tags
+    }
+
+    /// Returns the version of the specification used by the layer.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Paints the feature with the given painter.
+    ///
+    /// Features of unknown type are ignored.
+    pub fn paint<P: Painter + ?Sized>(&self, painter: &mut P) -> Result<()> {
+        if self.geom_type == GeomType::UNKNOWN {
+            return Ok(());
         }
-        rank.push_format(format_args!(" min-zoom-{}",
-            self.tags.get("min_zoom").and_then(Value::f32).unwrap_or(0f32).floor()));
-        rank.push('"');
-        if let Some(id) = self.id {
-            rank.push_format(format_args!(" data-id=\"{}\"", id));
+        painter.begin_feature(self)?;
+        match self.paint_commands(painter) {
+            Ok(()) => {
+                painter.end_feature();
+                Ok(())
+            },
+            Err(e) => {
+                // Do not leave half a feature in the output.
+                painter.abort_feature();
+                Err(e)
+            },
         }
-        Ok(())
     }
 
-    fn paint_description(&mut self, rank: &mut Rank, close_path: bool)
-                -> ProtobufResult<()> {
+    fn paint_commands<P: Painter + ?Sized>(&self, painter: &mut P) -> Result<()> {
         use cursor::Command::*;
-        rank.push_str(" d=\"");
+        // Note: multi-points are a single MoveTo command with a count
+        // greater than one, so every MoveTo is a separate point.
         for command in Cursor::new(self.geometry, self.scale) {
-            match command {
-                Ok(MoveTo(x, y)) => rank.push_format(
-                    format_args!("M {} {} ", x, y)),
-                Ok(LineTo(x, y)) => rank.push_format(
-                    format_args!("L {} {} ", x, y)),
-                Ok(ClosePath) => if close_path {
-                    rank.push_str("Z ");
-                } else {
-                    return Err(ProtobufError::WireError("close path not allowed".to_owned()))
-                },
-                Err(e) => return Err(e),
+            match (self.geom_type, command?) {
+                (_, MoveTo(x, y)) => painter.move_to(x, y),
+                (GeomType::LINESTRING, LineTo(x, y)) |
+                (GeomType::POLYGON, LineTo(x, y)) => painter.line_to(x, y),
+                (GeomType::POLYGON, ClosePath) => painter.close_path(),
+                (_, LineTo(..)) => return Err(Error::UnexpectedCommand {
+                    command: 2,
+                    geom_type: self.geom_type,
+                }),
+                (_, ClosePath) => return Err(Error::UnexpectedCommand {
+                    command: 7,
+                    geom_type: self.geom_type,
+                }),
             }
         }
-        rank.push_str("\"");
         Ok(())
     }
 }
+
+#[test]
+fn test_paint_points() {
+    use svg::SvgPainter;
+    use vector_tile::Tile_GeomType::POINT;
+
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_field_type(POINT);
+    // MoveTo with count 2: (25, 17) and (26, 19).
+    raw_feature.set_geometry(vec![17, 50, 34, 2, 4]);
+    let mut tags = TagMap::new();
+    tags.insert("kind", Value::String("city"));
+    tags.insert("name", Value::String("Frankfurt <Main>"));
+    let feature = Feature::new(&raw_feature, tags, "places", 2, 1.0).unwrap();
+    let mut painter = SvgPainter::new();
+    feature.paint(&mut painter).unwrap();
+    assert_eq!(String::from(painter).as_str(),
+        "<circle class=\"layer-places kind-city min-zoom-0\" cx=\"25\" cy=\"17\" r=\"1\"></circle>\n\
+         <text class=\"layer-places kind-city min-zoom-0\" x=\"25\" y=\"17\">Frankfurt &lt;Main&gt;</text>\n\
+         <circle class=\"layer-places kind-city min-zoom-0\" cx=\"26\" cy=\"19\" r=\"1\"></circle>\n\
+         <text class=\"layer-places kind-city min-zoom-0\" x=\"26\" y=\"19\">Frankfurt &lt;Main&gt;</text>\n");
+}
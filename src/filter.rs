@@ -0,0 +1,572 @@
+//! Filters select features by their tags and geometry type.
+//!
+//! The filters follow the filters of Mapbox GL styles, both the legacy
+//! syntax `["==", "kind", "lake"]` and the expression syntax
+//! `["==", ["get", "kind"], "lake"]` are read from JSON.
+//!
+//! For the command line filters are also written as text:
+//!
+//! ```text
+//! kind in (major_road, highway) && min_zoom <= 12
+//! has name and not $type == Point
+//! ```
+//!
+//! The special keys `$type` and `$id` refer to the geometry type
+//! (`Point`, `LineString` or `Polygon`) and the id of the feature.
+
+use serde_json::Value as Json;
+
+use error::{Error, Result};
+use feature::Feature;
+use tag::{TagMap, Value};
+use vector_tile::Tile_GeomType as GeomType;
+
+/// What a filter looks at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    /// The value of a tag.
+    Tag(String),
+    /// The geometry type as string.
+    GeometryType,
+    /// The id of the feature.
+    Id,
+}
+
+/// A constant in a filter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    /// A string.
+    String(String),
+    /// Any number.
+    Number(f64),
+    /// A boolean.
+    Bool(bool),
+}
+
+/// Compares a value with a literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+}
+
+/// A filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// A constant result.
+    Bool(bool),
+    /// All filters must match.
+    All(Vec<Filter>),
+    /// At least one filter must match.
+    Any(Vec<Filter>),
+    /// The filter must not match.
+    Not(Box<Filter>),
+    /// The key has a value.
+    Has(Key),
+    /// The value of the key compares to the literal.
+    ///
+    /// Values of different types never compare, except for `!=`
+    /// which matches them and missing values too.
+    Compare(Key, Comparison, Literal),
+    /// The value of the key equals one of the literals.
+    In(Key, Vec<Literal>),
+}
+
+/// The properties of a feature a filter can look at.
+#[derive(Debug, Clone, Copy)]
+pub struct Subject<'a, 'k: 'a, 'v: 'a> {
+    /// The tags.
+    pub tags: &'a TagMap<'k, 'v>,
+    /// The geometry type.
+    pub geom_type: GeomType,
+    /// The id, if the feature has one.
+    pub id: Option<u64>,
+}
+
+impl<'a, 'k, 'v> Subject<'a, 'k, 'v> {
+    /// The properties of a feature.
+    pub fn of(feature: &'a Feature<'_, 'k, 'v>) -> Subject<'a, 'k, 'v> {
+        Subject {
+            tags: feature.tags(),
+            geom_type: feature.geom_type(),
+            id: feature.id(),
+        }
+    }
+}
+
+/// A value of a subject.
+enum Found<'a> {
+    Value(&'a Value<'a>),
+    Str(&'static str),
+    Number(f64),
+}
+
+impl<'a> Found<'a> {
+    fn literal(&self) -> Option<Literal> {
+        Some(match *self {
+            Found::Value(&Value::String(s)) => Literal::String(s.to_owned()),
+            Found::Value(&Value::Bool(b)) => Literal::Bool(b),
+            Found::Value(value) => Literal::Number(value.number()?),
+            Found::Str(s) => Literal::String(s.to_owned()),
+            Found::Number(x) => Literal::Number(x),
+        })
+    }
+
+    fn equals(&self, literal: &Literal) -> bool {
+        match (self, literal) {
+            (Found::Value(Value::String(s)), Literal::String(l)) => s == l,
+            (Found::Str(s), Literal::String(l)) => s == l,
+            (Found::Value(Value::Bool(b)), Literal::Bool(l)) => b == l,
+            (_, Literal::Number(l)) => self.number() == Some(*l),
+            _ => false,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match *self {
+            Found::Value(value) => value.number(),
+            Found::Number(x) => Some(x),
+            Found::Str(_) => None,
+        }
+    }
+}
+
+impl Filter {
+    /// Returns true if the feature matches the filter.
+    pub fn matches(&self, subject: &Subject) -> bool {
+        match *self {
+            Filter::Bool(b) => b,
+            Filter::All(ref filters) => filters.iter().all(|f| f.matches(subject)),
+            Filter::Any(ref filters) => filters.iter().any(|f| f.matches(subject)),
+            Filter::Not(ref filter) => !filter.matches(subject),
+            Filter::Has(ref key) => find(key, subject).is_some(),
+            Filter::Compare(ref key, comparison, ref literal) => {
+                let found = match find(key, subject) {
+                    Some(found) => found,
+                    None => return comparison == Comparison::NotEqual,
+                };
+                match comparison {
+                    Comparison::Equal => found.equals(literal),
+                    Comparison::NotEqual => !found.equals(literal),
+                    _ => compare(&found, literal).is_some_and(|ordering| {
+                        use std::cmp::Ordering::*;
+                        matches!((comparison, ordering),
+                            (Comparison::Less, Less) |
+                            (Comparison::LessOrEqual, Less) | (Comparison::LessOrEqual, Equal) |
+                            (Comparison::Greater, Greater) |
+                            (Comparison::GreaterOrEqual, Greater) |
+                            (Comparison::GreaterOrEqual, Equal))
+                    }),
+                }
+            },
+            Filter::In(ref key, ref literals) => find(key, subject)
+                .is_some_and(|found| literals.iter().any(|l| found.equals(l))),
+        }
+    }
+
+    /// Returns true if the feature matches the filter.
+    pub fn matches_feature(&self, feature: &Feature) -> bool {
+        self.matches(&Subject::of(feature))
+    }
+
+    /// Reads a filter in the JSON syntax of Mapbox GL styles.
+    pub fn from_json(json: &Json) -> Result<Filter> {
+        let array = match *json {
+            Json::Bool(b) => return Ok(Filter::Bool(b)),
+            Json::Array(ref array) => array,
+            _ => return Err(Error::InvalidFilter("a filter must be an array")),
+        };
+        let (operator, args) = match array.split_first() {
+            Some((Json::String(operator), args)) => (operator.as_str(), args),
+            _ => return Err(Error::InvalidFilter("a filter must start with an operator")),
+        };
+        let filters = || args.iter().map(Filter::from_json).collect::<Result<Vec<_>>>();
+        let comparison = match operator {
+            "all" => return Ok(Filter::All(filters()?)),
+            "any" => return Ok(Filter::Any(filters()?)),
+            "none" => return Ok(Filter::Not(Box::new(Filter::Any(filters()?)))),
+            "!" => return match args {
+                [filter] => Ok(Filter::Not(Box::new(Filter::from_json(filter)?))),
+                _ => Err(Error::InvalidFilter("`!` needs one filter")),
+            },
+            "has" | "!has" => {
+                let has = match args {
+                    [key] => Filter::Has(key_from_json(key)?),
+                    _ => return Err(Error::InvalidFilter("`has` needs one key")),
+                };
+                return Ok(if operator == "has" { has } else { Filter::Not(Box::new(has)) });
+            },
+            "in" | "!in" => {
+                let (key, literals) = match args.split_first() {
+                    Some((key, literals)) => (key_from_json(key)?, literals),
+                    None => return Err(Error::InvalidFilter("`in` needs a key")),
+                };
+                let literals = match literals {
+                    // The expression syntax: ["in", ["get", key], ["literal", [values]]].
+                    [Json::Array(ref literal)] => match literal.as_slice() {
+                        [Json::String(ref name), Json::Array(ref values)] if name == "literal" =>
+                            values.iter().map(literal_from_json).collect(),
+                        _ => Err(Error::InvalidFilter("`in` needs a `literal` array")),
+                    },
+                    _ => literals.iter().map(literal_from_json).collect(),
+                };
+                let filter = Filter::In(key, literals?);
+                return Ok(if operator == "in" { filter } else { Filter::Not(Box::new(filter)) });
+            },
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return Err(Error::InvalidFilter("unknown operator")),
+        };
+        match args {
+            [key, literal] =>
+                Ok(Filter::Compare(key_from_json(key)?, comparison, literal_from_json(literal)?)),
+            _ => Err(Error::InvalidFilter("a comparison needs a key and a value")),
+        }
+    }
+
+    /// Reads a filter written as text.
+    ///
+    /// Comparisons are written as `key == value` with the operators
+    /// `==`, `!=`, `<`, `<=`, `>` and `>=`. `key in (a, b)` and
+    /// `key !in (a, b)` test a list of values, `has key` tests
+    /// whether a tag exists. Filters are combined with `&&` (`and`),
+    /// `||` (`or`), `!` (`not`) and parentheses; `&&` binds stronger
+    /// than `||`.
+    ///
+    /// Values are numbers, `true`, `false` or strings. Strings with
+    /// spaces, operators or the look of a number are quoted with `"`
+    /// or `'`.
+    pub fn parse(text: &str) -> Result<Filter> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0, depth: 0 };
+        let filter = parser.any()?;
+        match parser.next() {
+            None => Ok(filter),
+            Some(_) => Err(Error::InvalidFilter("unexpected text after the filter")),
+        }
+    }
+}
+
+/// A piece of a filter written as text.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    And,
+    Or,
+    Not,
+    Compare(Comparison),
+    /// An unquoted word: a key, keyword, number or string.
+    Word(String),
+    Quoted(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut followed_by = |next| chars.next_if_eq(&next).is_some();
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '&' if followed_by('&') => Token::And,
+            '|' if followed_by('|') => Token::Or,
+            '=' if followed_by('=') => Token::Compare(Comparison::Equal),
+            '!' if followed_by('=') => Token::Compare(Comparison::NotEqual),
+            '!' => Token::Not,
+            '<' if followed_by('=') => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if followed_by('=') => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            '"' | '\'' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(next) => quoted.push(next),
+                        None => return Err(Error::InvalidFilter("a quote is not closed")),
+                    }
+                }
+                Token::Quoted(quoted)
+            },
+            _ if is_word(c) => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|&next| is_word(next)) {
+                    word.push(next);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            },
+            _ => return Err(Error::InvalidFilter("unexpected character")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || "_-.:$+".contains(c)
+}
+
+/// Negations and parentheses may be nested at most this deep.
+const MAX_DEPTH: usize = 128;
+
+/// Reads tokens by recursive descent.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    // The nesting depth, limited to keep the stack small.
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position) == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token, message: &'static str) -> Result<()> {
+        if self.next_is(token) {
+            Ok(())
+        } else {
+            Err(Error::InvalidFilter(message))
+        }
+    }
+
+    /// Parses a nested filter.
+    fn nested(&mut self, parse: fn(&mut Parser) -> Result<Filter>) -> Result<Filter> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::InvalidFilter("the filter is nested too deep"));
+        }
+        self.depth += 1;
+        let filter = parse(self);
+        self.depth -= 1;
+        filter
+    }
+
+    // any := all ("||" all)*
+    fn any(&mut self) -> Result<Filter> {
+        let mut filters = vec![self.all()?];
+        while self.next_is(&Token::Or) {
+            filters.push(self.all()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { Filter::Any(filters) })
+    }
+
+    // all := unary ("&&" unary)*
+    fn all(&mut self) -> Result<Filter> {
+        let mut filters = vec![self.unary()?];
+        while self.next_is(&Token::And) {
+            filters.push(self.unary()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { Filter::All(filters) })
+    }
+
+    // unary := "!" unary | "(" any ")" | "has" key | "true" | "false" | key condition
+    fn unary(&mut self) -> Result<Filter> {
+        let key = match self.next() {
+            Some(Token::Not) => return Ok(Filter::Not(Box::new(self.nested(Parser::unary)?))),
+            Some(Token::Open) => {
+                let filter = self.nested(Parser::any)?;
+                self.expect(&Token::Close, "a parenthesis is not closed")?;
+                return Ok(filter);
+            },
+            Some(Token::Word(ref word)) if word == "has" => return Ok(Filter::Has(self.key()?)),
+            Some(Token::Word(ref word)) if word == "true" => return Ok(Filter::Bool(true)),
+            Some(Token::Word(ref word)) if word == "false" => return Ok(Filter::Bool(false)),
+            Some(Token::Word(key)) | Some(Token::Quoted(key)) => key_from_str(&key),
+            _ => return Err(Error::InvalidFilter("expected a key")),
+        };
+        match self.next() {
+            Some(Token::Compare(comparison)) => Ok(Filter::Compare(key, comparison, self.literal()?)),
+            Some(Token::Not) => {
+                self.expect(&Token::Word("in".to_owned()), "expected `in`")?;
+                Ok(Filter::Not(Box::new(Filter::In(key, self.list()?))))
+            },
+            Some(Token::Word(ref word)) if word == "in" => Ok(Filter::In(key, self.list()?)),
+            _ => Err(Error::InvalidFilter("expected a comparison or `in`")),
+        }
+    }
+
+    fn key(&mut self) -> Result<Key> {
+        match self.next() {
+            Some(Token::Word(key)) | Some(Token::Quoted(key)) => Ok(key_from_str(&key)),
+            _ => Err(Error::InvalidFilter("expected a key")),
+        }
+    }
+
+    // list := "(" literal ("," literal)* ")"
+    fn list(&mut self) -> Result<Vec<Literal>> {
+        self.expect(&Token::Open, "`in` needs a list in parentheses")?;
+        let mut literals = vec![self.literal()?];
+        while self.next_is(&Token::Comma) {
+            literals.push(self.literal()?);
+        }
+        self.expect(&Token::Close, "a parenthesis is not closed")?;
+        Ok(literals)
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        match self.next() {
+            Some(Token::Quoted(s)) => Ok(Literal::String(s)),
+            Some(Token::Word(word)) => Ok(match word.as_str() {
+                "true" => Literal::Bool(true),
+                "false" => Literal::Bool(false),
+                _ => match word.parse::<f64>() {
+                    Ok(x) if x.is_finite() => Literal::Number(x),
+                    _ => Literal::String(word),
+                },
+            }),
+            _ => Err(Error::InvalidFilter("expected a value")),
+        }
+    }
+}
+
+fn key_from_json(json: &Json) -> Result<Key> {
+    match *json {
+        Json::String(ref key) => Ok(key_from_str(key)),
+        Json::Array(ref expression) => match expression.as_slice() {
+            [Json::String(ref get), Json::String(ref key)] if get == "get" => Ok(Key::Tag(key.clone())),
+            [Json::String(ref name)] if name == "geometry-type" => Ok(Key::GeometryType),
+            [Json::String(ref name)] if name == "id" => Ok(Key::Id),
+            _ => Err(Error::InvalidFilter("unsupported expression")),
+        },
+        _ => Err(Error::InvalidFilter("a key must be a string")),
+    }
+}
+
+fn key_from_str(key: &str) -> Key {
+    match key {
+        "$type" => Key::GeometryType,
+        "$id" => Key::Id,
+        _ => Key::Tag(key.to_owned()),
+    }
+}
+
+fn literal_from_json(json: &Json) -> Result<Literal> {
+    match *json {
+        Json::String(ref s) => Ok(Literal::String(s.clone())),
+        Json::Number(ref n) => n.as_f64().map(Literal::Number)
+            .ok_or(Error::InvalidFilter("a number can not be represented as a float")),
+        Json::Bool(b) => Ok(Literal::Bool(b)),
+        _ => Err(Error::InvalidFilter("a value must be a string, number or boolean")),
+    }
+}
+
+fn find<'a>(key: &Key, subject: &Subject<'a, '_, 'a>) -> Option<Found<'a>> {
+    match *key {
+        Key::Tag(ref key) => subject.tags.get(key.as_str()).map(Found::Value),
+        Key::GeometryType => match subject.geom_type {
+            GeomType::POINT => Some(Found::Str("Point")),
+            GeomType::LINESTRING => Some(Found::Str("LineString")),
+            GeomType::POLYGON => Some(Found::Str("Polygon")),
+            GeomType::UNKNOWN => None,
+        },
+        Key::Id => subject.id.map(|id| Found::Number(id as f64)),
+    }
+}
+
+/// Orders numbers and strings, other types do not compare.
+fn compare(found: &Found, literal: &Literal) -> Option<::std::cmp::Ordering> {
+    match (found.literal()?, literal) {
+        (Literal::Number(a), &Literal::Number(b)) => a.partial_cmp(&b),
+        (Literal::String(ref a), Literal::String(b)) => Some(a.as_str().cmp(b)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_filter_json() {
+    use serde_json::json;
+
+    let mut tags = TagMap::new();
+    tags.insert("kind", Value::String("major_road"));
+    tags.insert("min_zoom", Value::Float32(11.5));
+    tags.insert("is_bridge", Value::Bool(true));
+    let subject = Subject { tags: &tags, geom_type: GeomType::LINESTRING, id: Some(7) };
+    let matches = |json: Json| Filter::from_json(&json).unwrap().matches(&subject);
+
+    assert!(matches(json!(["==", "kind", "major_road"])));
+    assert!(matches(json!(["==", ["get", "kind"], "major_road"])));
+    assert!(!matches(json!(["==", "kind", 1])));
+    assert!(matches(json!(["!=", "name", "x"])));
+    assert!(matches(json!(["in", "kind", "highway", "major_road"])));
+    assert!(matches(json!(["in", ["get", "kind"], ["literal", ["highway", "major_road"]]])));
+    assert!(!matches(json!(["!in", "kind", "highway", "major_road"])));
+    assert!(matches(json!(["has", "is_bridge"])));
+    assert!(matches(json!(["!has", "name"])));
+    assert!(matches(json!(["<=", "min_zoom", 12])));
+    assert!(!matches(json!([">", "min_zoom", 12])));
+    assert!(!matches(json!(["<", "kind", 12])));
+    assert!(matches(json!(["==", "is_bridge", true])));
+    assert!(matches(json!(["==", "$type", "LineString"])));
+    assert!(matches(json!(["==", ["geometry-type"], "LineString"])));
+    assert!(matches(json!(["==", "$id", 7])));
+    assert!(matches(json!(["all", ["has", "kind"], ["any", false, ["<", "min_zoom", 12]]])));
+    assert!(!matches(json!(["none", ["has", "kind"]])));
+    assert!(!matches(json!(["!", true])));
+
+    assert!(Filter::from_json(&json!("kind")).is_err());
+    assert!(Filter::from_json(&json!(["~", "kind", 1])).is_err());
+    assert!(Filter::from_json(&json!(["==", "kind"])).is_err());
+    assert!(Filter::from_json(&json!(["==", ["zoom"], 1])).is_err());
+}
+
+#[test]
+fn test_filter_parse() {
+    use serde_json::json;
+
+    let parse = |text| Filter::parse(text).unwrap();
+    assert_eq!(parse("kind in (major_road, highway) && min_zoom <= 12"),
+        Filter::from_json(&json!(
+            ["all", ["in", "kind", "major_road", "highway"], ["<=", "min_zoom", 12]])).unwrap());
+    assert_eq!(parse("a == 1 || b != 'x y' and !has c"),
+        Filter::from_json(&json!(
+            ["any", ["==", "a", 1], ["all", ["!=", "b", "x y"], ["!", ["has", "c"]]]])).unwrap());
+    assert_eq!(parse("not (is_bridge == true or $type == Point)"),
+        Filter::from_json(&json!(
+            ["!", ["any", ["==", "is_bridge", true], ["==", "$type", "Point"]]])).unwrap());
+    assert_eq!(parse("kind !in (\"1\", -2.5)"),
+        Filter::Not(Box::new(Filter::In(Key::Tag("kind".to_owned()),
+            vec![Literal::String("1".to_owned()), Literal::Number(-2.5)]))));
+    assert_eq!(parse("$id>=7"), Filter::Compare(Key::Id, Comparison::GreaterOrEqual, Literal::Number(7.0)));
+
+    for invalid in &["", "kind", "kind ==", "kind = 1", "(kind == 1", "kind in ()", "a == 1 b == 2",
+                     "kind == 'x", "kind == #"] {
+        assert!(Filter::parse(invalid).is_err(), "{}", invalid);
+    }
+    let nested = |depth| format!("{}true{}", "!(".repeat(depth), ")".repeat(depth));
+    assert!(Filter::parse(&nested(64)).is_ok());
+    assert!(Filter::parse(&nested(65)).is_err());
+    assert!(Filter::parse(&"!".repeat(100_000)).is_err());
+}
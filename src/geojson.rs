@@ -0,0 +1,274 @@
+//! Exports the contents of map tiles as GeoJSON.
+//!
+//! Each tile or layer becomes a FeatureCollection. Tags are written
+//! as properties and the layer name is stored in the foreign member
+//! `layer` of each feature.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::io::Read;
+
+use protobuf;
+use serde_json::Value as Json;
+
+use compression;
+use error::{Error, Result};
+use geometry::{Geometry, InvalidRings, Point};
+use layer::Layer;
+use options::Options;
+use projection::TileAddress;
+use tag::{TagMap, Value};
+use vector_tile::{Tile, Tile_Feature, Tile_GeomType as GeomType, Tile_Layer};
+
+/// The coordinate system used in the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coordinates {
+    /// Coordinates in tile units from 0 to the layer's extent.
+    ///
+    /// (0, 0) is in the upper left corner of the tile.
+    TileLocal,
+    /// Longitude and latitude (WGS84) for the tile with the given address.
+    Wgs84(TileAddress),
+}
+
+impl Coordinates {
+    fn transform(&self, extent: u32, x: i32, y: i32) -> (f64, f64) {
+        let (x, y) = (f64::from(x), f64::from(y));
+        match *self {
+            Coordinates::TileLocal => (x, y),
+            Coordinates::Wgs84(ref address) => address.tile_to_lon_lat(extent, x, y),
+        }
+    }
+}
+
+/// Reads a Vector File and produces a GeoJSON FeatureCollection with all features.
+///
+/// Gzip and zlib compressed files are decompressed.
+pub fn process<R: Read>(r: R, coordinates: Coordinates) -> Result<String> {
+    process_with_options(r, coordinates, &Options::default())
+}
+
+/// Reads a Vector File and produces a GeoJSON FeatureCollection using the given options.
+///
+/// Only `decompress` applies, all features are exported.
+pub fn process_with_options<R: Read>(mut r: R, coordinates: Coordinates, options: &Options)
+        -> Result<String> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data).map_err(Error::Io)?;
+    let data = if options.decompress {
+        compression::decompress(&data)?
+    } else {
+        Cow::Borrowed(&data[..])
+    };
+    let tile: Tile = protobuf::parse_from_bytes(&data)?;
+    tile_to_geojson(&tile, coordinates)
+}
+
+/// Converts all layers of a tile to a single FeatureCollection.
+pub fn tile_to_geojson(tile: &Tile, coordinates: Coordinates) -> Result<String> {
+    let mut out = String::new();
+    out.push_str("{\"type\":\"FeatureCollection\",\"features\":[");
+    let mut first = true;
+    for raw_layer in tile.get_layers() {
+        write_features(&mut out, raw_layer, coordinates, &mut first)?;
+    }
+    out.push_str("]}");
+    Ok(out)
+}
+
+/// Converts a single layer to a FeatureCollection.
+pub fn layer_to_geojson(raw_layer: &Tile_Layer, coordinates: Coordinates) -> Result<String> {
+    let mut out = String::new();
+    out.push_str("{\"type\":\"FeatureCollection\",\"features\":[");
+    write_features(&mut out, raw_layer, coordinates, &mut true)?;
+    out.push_str("]}");
+    Ok(out)
+}
+
+fn write_features(out: &mut String, raw_layer: &Tile_Layer, coordinates: Coordinates,
+                  first: &mut bool) -> Result<()> {
+    let layer = Layer::new(raw_layer);
+    layer.check()?;
+    let name = raw_layer.get_name();
+    for (index, raw_feature) in raw_layer.get_features().iter().enumerate() {
+        if raw_feature.get_field_type() == GeomType::UNKNOWN {
+            continue;
+        }
+        if !*first {
+            out.push(',');
+        }
+        *first = false;
+        write_feature(out, &layer, raw_layer, raw_feature, coordinates)
+            .map_err(|e| e.in_feature(name, index))?;
+    }
+    Ok(())
+}
+
+fn write_feature(out: &mut String, layer: &Layer, raw_layer: &Tile_Layer,
+                 raw_feature: &Tile_Feature, coordinates: Coordinates) -> Result<()> {
+    let tags = layer.get_tags(raw_feature.get_tags())?;
+    out.push_str("{\"type\":\"Feature\"");
+    if raw_feature.has_id() {
+        write!(out, ",\"id\":{}", raw_feature.get_id()).unwrap();
+    }
+    write!(out, ",\"layer\":{}", Json::from(raw_layer.get_name())).unwrap();
+    out.push_str(",\"geometry\":");
+    // Invalid rings are dropped, like browsers do when drawing the SVG.
+    let geometry = Geometry::decode(raw_feature.get_field_type(), raw_feature.get_geometry(),
+                                    layer.version(), InvalidRings::Repair)?;
+    write_geometry(out, &geometry, raw_layer.get_extent(), coordinates);
+    out.push_str(",\"properties\":");
+    write_properties(out, &tags);
+    out.push('}');
+    Ok(())
+}
+
+fn write_geometry(out: &mut String, geometry: &Geometry, extent: u32, coordinates: Coordinates) {
+    let position = |out: &mut String, &(x, y): &Point| {
+        let (x, y) = coordinates.transform(extent, x, y);
+        write!(out, "[{},{}]", x, y).unwrap();
+    };
+    let line = |out: &mut String, line: &[Point]| {
+        out.push('[');
+        for (i, p) in line.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            position(out, p);
+        }
+        out.push(']');
+    };
+    let lines = |out: &mut String, lines: &mut dyn Iterator<Item = &[Point]>| {
+        out.push('[');
+        for (i, ring) in lines.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            line(out, ring);
+        }
+        out.push(']');
+    };
+    match *geometry {
+        Geometry::Point(ref point) => {
+            out.push_str("{\"type\":\"Point\",\"coordinates\":");
+            position(out, point);
+        },
+        Geometry::MultiPoint(ref points) => {
+            out.push_str("{\"type\":\"MultiPoint\",\"coordinates\":");
+            line(out, points);
+        },
+        Geometry::LineString(ref points) => {
+            out.push_str("{\"type\":\"LineString\",\"coordinates\":");
+            line(out, points);
+        },
+        Geometry::MultiLineString(ref parts) => {
+            out.push_str("{\"type\":\"MultiLineString\",\"coordinates\":");
+            lines(out, &mut parts.iter().map(|part| &part[..]));
+        },
+        Geometry::Polygon(ref polygon) => {
+            out.push_str("{\"type\":\"Polygon\",\"coordinates\":");
+            lines(out, &mut polygon.rings());
+        },
+        Geometry::MultiPolygon(ref multi) => {
+            out.push_str("{\"type\":\"MultiPolygon\",\"coordinates\":[");
+            for (i, polygon) in multi.polygons().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                lines(out, &mut polygon.rings());
+            }
+            out.push(']');
+        },
+    }
+    out.push('}');
+}
+
+fn write_properties(out: &mut String, tags: &TagMap) {
+    // Sort the keys to produce the same output for the same tile.
+    let mut keys: Vec<&&str> = tags.keys().collect();
+    keys.sort();
+    out.push('{');
+    for (i, key) in keys.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "{}:", Json::from(*key)).unwrap();
+        match tags[key] {
+            Value::String(s) => write!(out, "{}", Json::from(s)),
+            Value::Float32(x) if x.is_finite() => write!(out, "{}", x),
+            Value::Float64(x) if x.is_finite() => write!(out, "{}", x),
+            Value::Float32(_) | Value::Float64(_) => write!(out, "null"),
+            Value::Int64(x) => write!(out, "{}", x),
+            Value::Uint64(x) => write!(out, "{}", x),
+            Value::Bool(x) => write!(out, "{}", x),
+        }.unwrap();
+    }
+    out.push('}');
+}
+
+#[test]
+fn test_layer_to_geojson() {
+    use protobuf::RepeatedField;
+    use vector_tile::Tile_Value;
+
+    let mut kind = Tile_Value::new();
+    kind.set_string_value("lake \"blue\"".to_owned());
+    let mut area = Tile_Value::new();
+    area.set_double_value(1.5);
+    let mut point = Tile_Feature::new();
+    point.set_id(7);
+    point.set_field_type(GeomType::POINT);
+    point.set_tags(vec![0, 0]);
+    point.set_geometry(vec![9, 4, 4]);
+    // A square with a hole: exterior clockwise, interior counter-clockwise (y down).
+    let mut polygon = Tile_Feature::new();
+    polygon.set_field_type(GeomType::POLYGON);
+    polygon.set_tags(vec![0, 0, 1, 1]);
+    polygon.set_geometry(vec![
+        9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15,
+        9, 4, 15, 26, 0, 4, 4, 0, 0, 3, 15,
+    ]);
+    let mut line = Tile_Feature::new();
+    line.set_field_type(GeomType::LINESTRING);
+    line.set_geometry(vec![9, 0, 0, 10, 2, 2, 9, 2, 2, 10, 2, 2]);
+    let mut raw_layer = Tile_Layer::new();
+    raw_layer.set_name("water".to_owned());
+    raw_layer.set_keys(RepeatedField::from_vec(vec!["kind".to_owned(), "area".to_owned()]));
+    raw_layer.set_values(RepeatedField::from_vec(vec![kind, area]));
+    raw_layer.set_features(RepeatedField::from_vec(vec![point, polygon, line]));
+
+    assert_eq!(layer_to_geojson(&raw_layer, Coordinates::TileLocal).unwrap(),
+        "{\"type\":\"FeatureCollection\",\"features\":[\
+         {\"type\":\"Feature\",\"id\":7,\"layer\":\"water\",\
+         \"geometry\":{\"type\":\"Point\",\"coordinates\":[2,2]},\
+         \"properties\":{\"kind\":\"lake \\\"blue\\\"\"}},\
+         {\"type\":\"Feature\",\"layer\":\"water\",\
+         \"geometry\":{\"type\":\"Polygon\",\"coordinates\":[\
+         [[0,0],[10,0],[10,10],[0,10],[0,0]],[[2,2],[2,4],[4,4],[4,2],[2,2]]]},\
+         \"properties\":{\"area\":1.5,\"kind\":\"lake \\\"blue\\\"\"}},\
+         {\"type\":\"Feature\",\"layer\":\"water\",\
+         \"geometry\":{\"type\":\"MultiLineString\",\"coordinates\":[\
+         [[0,0],[1,1]],[[2,2],[3,3]]]},\"properties\":{}}]}");
+}
+
+#[test]
+fn test_process_compressed() {
+    let geojson = process(&include_bytes!("../web/tile.mvt")[..], Coordinates::TileLocal).unwrap();
+    assert!(geojson.starts_with("{\"type\":\"FeatureCollection\",\"features\":[{"));
+    let options = Options { decompress: false, ..Options::default() };
+    assert!(process_with_options(&include_bytes!("../web/tile.mvt")[..], Coordinates::TileLocal,
+                                 &options).is_err());
+}
+
+#[test]
+fn test_wgs84_coordinates() {
+    let coordinates = Coordinates::Wgs84(TileAddress { z: 1, x: 1, y: 0 });
+    let (lon, lat) = coordinates.transform(4096, 0, 4096);
+    assert!(lon.abs() < 1e-9 && lat.abs() < 1e-9);
+    let (lon, lat) = coordinates.transform(4096, 4096, 0);
+    assert!((lon - 180.0).abs() < 1e-9);
+    assert!((lat - 85.0511287798).abs() < 1e-6);
+    // 2^24 + 1 has no exact f32 representation.
+    assert_eq!(Coordinates::TileLocal.transform(4096, 16_777_217, -1), (16_777_217.0, -1.0));
+}
+
@@ -0,0 +1,544 @@
+//! Decoded geometries in tile coordinates.
+//!
+//! `Cursor` yields a flat stream of commands, `Geometry` restores the
+//! structure: points, linestrings and polygons with their holes.
+//!
+//! Polygon geometries are a flat list of rings. Exterior rings start a
+//! new polygon and are followed by their holes, the kind of a ring is
+//! given by the sign of its area (spec 4.3.4.4).
+//!
+//! With the `geo-types` feature geometries convert into the types of
+//! the `geo-types` crate.
+
+use cursor::{Command, Cursor};
+use error::{Error, Result};
+use vector_tile::Tile_GeomType as GeomType;
+
+/// A point in tile coordinates.
+///
+/// (0, 0) is in the upper left corner of the tile.
+pub type Point = (i32, i32);
+
+/// The smallest rectangle containing a geometry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    /// The upper left corner.
+    pub min: Point,
+    /// The lower right corner.
+    pub max: Point,
+}
+
+impl BoundingBox {
+    fn of<'a, I: IntoIterator<Item = &'a Point>>(points: I) -> Option<BoundingBox> {
+        points.into_iter().fold(None, |bbox, &(x, y)| Some(match bbox {
+            None => BoundingBox { min: (x, y), max: (x, y) },
+            Some(BoundingBox { min, max }) => BoundingBox {
+                min: (min.0.min(x), min.1.min(y)),
+                max: (max.0.max(x), max.1.max(y)),
+            },
+        }))
+    }
+}
+
+/// How rings and linestrings that violate the specification are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidRings {
+    /// Fail with an error.
+    Reject,
+    /// Drop linestrings with less than two points and rings with less
+    /// than four points or without an area, close rings without a
+    /// ClosePath and turn holes without an exterior ring into exterior
+    /// rings.
+    Repair,
+}
+
+/// The signed area of a ring.
+///
+/// Rings with a positive area are clockwise in tile coordinates,
+/// these are exterior rings in version 2 of the specification.
+pub fn signed_area(ring: &[Point]) -> f64 {
+    let mut sum = 0i128;
+    for (a, b) in ring.iter().zip(ring.iter().skip(1).chain(ring.first())) {
+        sum += a.0 as i128 * b.1 as i128 - b.0 as i128 * a.1 as i128;
+    }
+    sum as f64 / 2.0
+}
+
+/// A polygon with an exterior ring and any number of holes.
+///
+/// Rings are closed, their last point equals the first one. Exterior
+/// rings have a positive and holes a negative area, whatever the winding
+/// order in the tile was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    exterior: Vec<Point>,
+    holes: Vec<Vec<Point>>,
+}
+
+impl Polygon {
+    /// Creates a polygon from an exterior ring and its holes.
+    ///
+    /// Rings may be given in any orientation, closed or not. Fails if a
+    /// ring has less than three distinct points or no area.
+    pub fn new(exterior: Vec<Point>, holes: Vec<Vec<Point>>) -> Result<Polygon> {
+        Ok(Polygon {
+            exterior: oriented(exterior, true)?,
+            holes: holes.into_iter().map(|hole| oriented(hole, false)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// The exterior ring.
+    pub fn exterior(&self) -> &[Point] {
+        &self.exterior
+    }
+
+    /// The holes of the polygon.
+    pub fn holes(&self) -> &[Vec<Point>] {
+        &self.holes
+    }
+
+    /// Iterates over the exterior ring followed by the holes.
+    pub fn rings(&self) -> impl Iterator<Item = &[Point]> {
+        Some(&self.exterior[..]).into_iter().chain(self.holes.iter().map(|hole| &hole[..]))
+    }
+
+    /// The area of the exterior ring minus the area of the holes.
+    pub fn area(&self) -> f64 {
+        self.rings().map(signed_area).sum()
+    }
+
+    /// The bounding box of the exterior ring.
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        BoundingBox::of(&self.exterior)
+    }
+}
+
+/// One or more polygons of a feature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultiPolygon {
+    polygons: Vec<Polygon>,
+}
+
+impl MultiPolygon {
+    /// Creates a geometry of several polygons.
+    pub fn new(polygons: Vec<Polygon>) -> MultiPolygon {
+        MultiPolygon { polygons }
+    }
+
+    /// Decodes the geometry of a polygon feature.
+    ///
+    /// `version` is the version of the specification used by the layer.
+    pub fn decode(geometry: &[u32], version: u32, invalid: InvalidRings) -> Result<MultiPolygon> {
+        let unexpected = |command: u32| Error::UnexpectedCommand {
+            command,
+            geom_type: GeomType::POLYGON,
+        };
+        let mut rings: Vec<Vec<Point>> = Vec::new();
+        let mut closed = true;
+        let mut cursor = Cursor::new(geometry, 1.0);
+        while let Some(command) = cursor.next() {
+            match command? {
+                Command::MoveTo(..) => {
+                    if !closed {
+                        close_ring(rings.last_mut(), invalid)?;
+                    }
+                    rings.push(vec![cursor.position()]);
+                    closed = false;
+                },
+                Command::LineTo(..) if !closed => rings.last_mut().unwrap().push(cursor.position()),
+                Command::LineTo(..) => return Err(unexpected(2)),
+                Command::ClosePath if !closed => {
+                    let ring = rings.last_mut().unwrap();
+                    let start = ring[0];
+                    ring.push(start);
+                    closed = true;
+                },
+                Command::ClosePath => return Err(unexpected(7)),
+            }
+        }
+        if !closed {
+            close_ring(rings.last_mut(), invalid)?;
+        }
+        MultiPolygon::from_rings(rings, version, invalid)
+    }
+
+    /// Groups closed rings into polygons.
+    ///
+    /// Version 2 exterior rings have a positive area. Version 1 does not
+    /// define the winding order, there exterior rings have the same
+    /// winding order as the first ring.
+    pub fn from_rings(rings: Vec<Vec<Point>>, version: u32, invalid: InvalidRings)
+            -> Result<MultiPolygon> {
+        let mut polygons: Vec<Polygon> = Vec::new();
+        let mut exterior_sign = 1.0;
+        for mut ring in rings {
+            let area = signed_area(&ring);
+            let reason = if ring.len() < 4 {
+                "a ring needs at least four points"
+            } else if area == 0.0 {
+                "a ring must have an area"
+            } else {
+                ""
+            };
+            if !reason.is_empty() {
+                match invalid {
+                    InvalidRings::Reject => return Err(Error::InvalidGeometry(reason)),
+                    InvalidRings::Repair => continue,
+                }
+            }
+            if version < 2 && polygons.is_empty() {
+                exterior_sign = area.signum();
+            }
+            let exterior = area * exterior_sign > 0.0;
+            // Store exterior rings with a positive area and holes with a negative one.
+            if (area > 0.0) != exterior {
+                ring.reverse();
+            }
+            match polygons.last_mut() {
+                Some(polygon) if !exterior => polygon.holes.push(ring),
+                None if !exterior && invalid == InvalidRings::Reject =>
+                    return Err(Error::InvalidGeometry("the first ring must be an exterior ring")),
+                None if !exterior => {
+                    ring.reverse();
+                    polygons.push(Polygon { exterior: ring, holes: Vec::new() });
+                },
+                _ => polygons.push(Polygon { exterior: ring, holes: Vec::new() }),
+            }
+        }
+        if polygons.is_empty() && invalid == InvalidRings::Reject {
+            return Err(Error::InvalidGeometry("a polygon geometry needs a ring"));
+        }
+        Ok(MultiPolygon { polygons })
+    }
+
+    /// The polygons.
+    pub fn polygons(&self) -> &[Polygon] {
+        &self.polygons
+    }
+
+    /// Returns the polygons.
+    pub fn into_polygons(self) -> Vec<Polygon> {
+        self.polygons
+    }
+
+    /// Returns `true` if no valid ring was left.
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty()
+    }
+
+    /// The sum of the areas of all polygons.
+    pub fn area(&self) -> f64 {
+        self.polygons.iter().map(Polygon::area).sum()
+    }
+
+    /// The bounding box of all exterior rings.
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        BoundingBox::of(self.polygons.iter().flat_map(|polygon| polygon.exterior()))
+    }
+}
+
+/// The decoded geometry of a feature.
+///
+/// Geometries with a single part use the single variants. In repair
+/// mode a geometry without valid parts is an empty multi geometry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    /// A single point.
+    Point(Point),
+    /// Several points.
+    MultiPoint(Vec<Point>),
+    /// A single linestring.
+    LineString(Vec<Point>),
+    /// Several linestrings.
+    MultiLineString(Vec<Vec<Point>>),
+    /// A single polygon.
+    Polygon(Polygon),
+    /// Several polygons.
+    MultiPolygon(MultiPolygon),
+}
+
+/// A part of a geometry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Part<'a> {
+    /// A point.
+    Point(Point),
+    /// A linestring.
+    LineString(&'a [Point]),
+    /// A polygon.
+    Polygon(&'a Polygon),
+}
+
+impl Geometry {
+    /// Decodes the geometry of a feature.
+    ///
+    /// `version` is the version of the specification used by the layer.
+    pub fn decode(geom_type: GeomType, geometry: &[u32], version: u32, invalid: InvalidRings)
+            -> Result<Geometry> {
+        let unexpected = |command: &Command| Error::UnexpectedCommand {
+            command: match *command { Command::MoveTo(..) => 1, Command::LineTo(..) => 2, Command::ClosePath => 7 },
+            geom_type,
+        };
+        let mut lines: Vec<Vec<Point>> = Vec::new();
+        match geom_type {
+            GeomType::POINT | GeomType::LINESTRING => {
+                let mut cursor = Cursor::new(geometry, 1.0);
+                while let Some(command) = cursor.next() {
+                    match (geom_type, command?) {
+                        (_, Command::MoveTo(..)) => lines.push(vec![cursor.position()]),
+                        (GeomType::LINESTRING, Command::LineTo(..)) if !lines.is_empty() =>
+                            lines.last_mut().unwrap().push(cursor.position()),
+                        (_, command) => return Err(unexpected(&command)),
+                    }
+                }
+            },
+            GeomType::POLYGON => {
+                let mut polygons = MultiPolygon::decode(geometry, version, invalid)?.into_polygons();
+                return Ok(if polygons.len() == 1 {
+                    Geometry::Polygon(polygons.pop().unwrap())
+                } else {
+                    Geometry::MultiPolygon(MultiPolygon { polygons })
+                });
+            },
+            GeomType::UNKNOWN => return Err(Error::InvalidGeometry("the geometry type is unknown")),
+        }
+        if geom_type == GeomType::POINT {
+            let mut points: Vec<Point> = lines.into_iter().map(|p| p[0]).collect();
+            return match points.len() {
+                0 if invalid == InvalidRings::Reject =>
+                    Err(Error::InvalidGeometry("a point geometry needs a point")),
+                1 => Ok(Geometry::Point(points.pop().unwrap())),
+                _ => Ok(Geometry::MultiPoint(points)),
+            };
+        }
+        if lines.iter().any(|line| line.len() < 2) {
+            if invalid == InvalidRings::Reject {
+                return Err(Error::InvalidGeometry("a linestring needs two points"));
+            }
+            lines.retain(|line| line.len() >= 2);
+        }
+        match lines.len() {
+            0 if invalid == InvalidRings::Reject =>
+                Err(Error::InvalidGeometry("a linestring geometry needs a line")),
+            1 => Ok(Geometry::LineString(lines.pop().unwrap())),
+            _ => Ok(Geometry::MultiLineString(lines)),
+        }
+    }
+
+    /// The geometry type of the feature this geometry belongs to.
+    pub fn geom_type(&self) -> GeomType {
+        match *self {
+            Geometry::Point(_) | Geometry::MultiPoint(_) => GeomType::POINT,
+            Geometry::LineString(_) | Geometry::MultiLineString(_) => GeomType::LINESTRING,
+            Geometry::Polygon(_) | Geometry::MultiPolygon(_) => GeomType::POLYGON,
+        }
+    }
+
+    /// Iterates over the points, linestrings or polygons.
+    pub fn parts(&self) -> Box<dyn Iterator<Item = Part<'_>> + '_> {
+        match *self {
+            Geometry::Point(point) => Box::new(Some(Part::Point(point)).into_iter()),
+            Geometry::MultiPoint(ref points) => Box::new(points.iter().map(|&p| Part::Point(p))),
+            Geometry::LineString(ref line) => Box::new(Some(Part::LineString(&line[..])).into_iter()),
+            Geometry::MultiLineString(ref lines) =>
+                Box::new(lines.iter().map(|line| Part::LineString(&line[..]))),
+            Geometry::Polygon(ref polygon) => Box::new(Some(Part::Polygon(polygon)).into_iter()),
+            Geometry::MultiPolygon(ref multi) => Box::new(multi.polygons.iter().map(Part::Polygon)),
+        }
+    }
+
+    /// Iterates over the rings of all polygons.
+    ///
+    /// Points and linestrings have no rings.
+    pub fn rings(&self) -> Box<dyn Iterator<Item = &[Point]> + '_> {
+        Box::new(self.parts().flat_map(|part| match part {
+            Part::Polygon(polygon) => Some(polygon.rings()),
+            _ => None,
+        }).flatten())
+    }
+
+    /// The bounding box of all points, `None` for empty geometries.
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        match *self {
+            Geometry::Point(ref point) => BoundingBox::of(Some(point)),
+            Geometry::MultiPoint(ref points) | Geometry::LineString(ref points) => BoundingBox::of(points),
+            Geometry::MultiLineString(ref lines) => BoundingBox::of(lines.iter().flatten()),
+            // Holes are inside the exterior ring.
+            Geometry::Polygon(ref polygon) => polygon.bbox(),
+            Geometry::MultiPolygon(ref multi) => multi.bbox(),
+        }
+    }
+}
+
+/// Closes a ring and gives it a positive area if it is an exterior ring.
+fn oriented(mut ring: Vec<Point>, exterior: bool) -> Result<Vec<Point>> {
+    ring.dedup();
+    match (ring.first(), ring.last()) {
+        (Some(&first), Some(last)) if first != *last => ring.push(first),
+        _ => (),
+    }
+    if ring.len() < 4 {
+        return Err(Error::InvalidGeometry("a ring needs three distinct points"));
+    }
+    let area = signed_area(&ring);
+    if area == 0.0 {
+        return Err(Error::InvalidGeometry("a ring must have an area"));
+    }
+    if (area > 0.0) != exterior {
+        ring.reverse();
+    }
+    Ok(ring)
+}
+
+fn close_ring(ring: Option<&mut Vec<Point>>, invalid: InvalidRings) -> Result<()> {
+    match (ring, invalid) {
+        (Some(ring), InvalidRings::Repair) => {
+            let start = ring[0];
+            ring.push(start);
+            Ok(())
+        },
+        _ => Err(Error::InvalidGeometry("a ring must end with a ClosePath")),
+    }
+}
+
+#[cfg(feature = "geo-types")]
+mod geo {
+    use geo_types;
+
+    use super::{Geometry, MultiPolygon, Point, Polygon};
+
+    fn line_string(line: &[Point]) -> geo_types::LineString<i32> {
+        line.iter().map(|&(x, y)| geo_types::Coord { x, y }).collect()
+    }
+
+    impl From<&Polygon> for geo_types::Polygon<i32> {
+        fn from(polygon: &Polygon) -> geo_types::Polygon<i32> {
+            geo_types::Polygon::new(line_string(&polygon.exterior),
+                                    polygon.holes.iter().map(|hole| line_string(hole)).collect())
+        }
+    }
+
+    impl From<&MultiPolygon> for geo_types::MultiPolygon<i32> {
+        fn from(multi: &MultiPolygon) -> geo_types::MultiPolygon<i32> {
+            multi.polygons.iter().map(geo_types::Polygon::from).collect()
+        }
+    }
+
+    impl From<&Geometry> for geo_types::Geometry<i32> {
+        fn from(geometry: &Geometry) -> geo_types::Geometry<i32> {
+            let point = |&(x, y): &Point| geo_types::Point::new(x, y);
+            match *geometry {
+                Geometry::Point(ref p) => point(p).into(),
+                Geometry::MultiPoint(ref points) =>
+                    geo_types::MultiPoint(points.iter().map(point).collect()).into(),
+                Geometry::LineString(ref line) => line_string(line).into(),
+                Geometry::MultiLineString(ref lines) =>
+                    geo_types::MultiLineString(lines.iter().map(|line| line_string(line)).collect()).into(),
+                Geometry::Polygon(ref polygon) => geo_types::Polygon::from(polygon).into(),
+                Geometry::MultiPolygon(ref multi) => geo_types::MultiPolygon::from(multi).into(),
+            }
+        }
+    }
+
+    impl From<Geometry> for geo_types::Geometry<i32> {
+        fn from(geometry: Geometry) -> geo_types::Geometry<i32> {
+            geo_types::Geometry::from(&geometry)
+        }
+    }
+}
+
+#[test]
+fn test_classify_rings() {
+    let square = |x: i32, size: i32| vec![(x, x), (x + size, x), (x + size, x + size), (x, x + size), (x, x)];
+    let reversed = |mut ring: Vec<Point>| { ring.reverse(); ring };
+    assert_eq!(signed_area(&square(0, 10)), 100.0);
+    let rings = vec![square(0, 10), reversed(square(2, 2)), square(20, 5)];
+    let multi = MultiPolygon::from_rings(rings, 2, InvalidRings::Reject).unwrap();
+    assert_eq!(multi.polygons().iter().map(|p| p.rings().count()).collect::<Vec<_>>(), vec![2, 1]);
+    assert_eq!(multi.area(), 100.0 - 4.0 + 25.0);
+
+    // Version 1 producers often wrote counter-clockwise exterior rings.
+    let rings = vec![reversed(square(0, 10)), square(2, 2), reversed(square(20, 5))];
+    let multi = MultiPolygon::from_rings(rings.clone(), 1, InvalidRings::Reject).unwrap();
+    assert_eq!(multi.polygons().iter().map(|p| p.rings().count()).collect::<Vec<_>>(), vec![2, 1]);
+    assert_eq!(multi.polygons()[0].exterior(), &square(0, 10)[..]);
+    assert_eq!(multi.area(), 121.0);
+    // Version 2 treats them as holes.
+    assert!(MultiPolygon::from_rings(rings.clone(), 2, InvalidRings::Reject).is_err());
+    let multi = MultiPolygon::from_rings(rings, 2, InvalidRings::Repair).unwrap();
+    assert_eq!(multi.polygons().iter().map(|p| p.rings().count()).collect::<Vec<_>>(), vec![1, 2]);
+
+    // New polygons are closed and oriented whatever the input was.
+    let polygon = Polygon::new(vec![(0, 0), (0, 10), (10, 10), (10, 0)], vec![square(2, 2)]).unwrap();
+    assert_eq!(polygon.exterior(), &square(0, 10)[..]);
+    assert_eq!(polygon.area(), 96.0);
+    assert!(Polygon::new(vec![(0, 0), (1, 1), (2, 2)], Vec::new()).is_err());
+    assert!(Polygon::new(vec![(0, 0), (1, 1), (1, 1), (0, 0)], Vec::new()).is_err());
+    let max = i32::MAX;
+    assert_eq!(signed_area(&[(-max, -max), (max, -max), (max, max), (-max, max)]),
+               2.0 * max as f64 * 2.0 * max as f64);
+}
+
+#[test]
+fn test_decode_polygons() {
+    // A square with a hole and a degenerate ring with two points.
+    let geometry = [
+        9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15,
+        9, 4, 15, 26, 0, 4, 4, 0, 0, 3, 15,
+        9, 0, 0, 10, 2, 2, 15,
+    ];
+    assert!(MultiPolygon::decode(&geometry, 2, InvalidRings::Reject).is_err());
+    let multi = MultiPolygon::decode(&geometry, 2, InvalidRings::Repair).unwrap();
+    assert_eq!(multi.polygons().len(), 1);
+    assert_eq!(multi.polygons()[0].exterior(), &[(0, 0), (10, 0), (10, 10), (0, 10), (0, 0)]);
+    assert_eq!(multi.polygons()[0].holes(), &[vec![(2, 2), (2, 4), (4, 4), (4, 2), (2, 2)]]);
+    assert_eq!(multi.area(), 96.0);
+
+    // The last ring lacks a ClosePath.
+    let geometry = &geometry[..21];
+    assert!(MultiPolygon::decode(geometry, 2, InvalidRings::Reject).is_err());
+    assert_eq!(MultiPolygon::decode(geometry, 2, InvalidRings::Repair).unwrap().area(), 96.0);
+    assert!(MultiPolygon::decode(&[10, 2, 2], 2, InvalidRings::Repair).is_err());
+}
+
+#[test]
+fn test_decode_geometry() {
+    let point = Geometry::decode(GeomType::POINT, &[9, 50, 34], 2, InvalidRings::Reject).unwrap();
+    assert_eq!(point, Geometry::Point((25, 17)));
+    let points = Geometry::decode(GeomType::POINT, &[17, 50, 34, 2, 4], 2, InvalidRings::Reject).unwrap();
+    assert_eq!(points, Geometry::MultiPoint(vec![(25, 17), (26, 19)]));
+    assert_eq!(points.parts().count(), 2);
+    assert_eq!(points.bbox(), Some(BoundingBox { min: (25, 17), max: (26, 19) }));
+    assert!(Geometry::decode(GeomType::POINT, &[9, 0, 0, 10, 2, 2], 2, InvalidRings::Repair).is_err());
+
+    // Two lines and a line with a single point.
+    let geometry = [9, 4, 4, 18, 2, 0, 0, 6, 9, 0, 0, 9, 2, 2, 10, 1, 1];
+    assert!(Geometry::decode(GeomType::LINESTRING, &geometry, 2, InvalidRings::Reject).is_err());
+    let lines = Geometry::decode(GeomType::LINESTRING, &geometry, 2, InvalidRings::Repair).unwrap();
+    assert_eq!(lines, Geometry::MultiLineString(vec![vec![(2, 2), (3, 2), (3, 5)], vec![(4, 6), (3, 5)]]));
+    assert_eq!(lines.bbox(), Some(BoundingBox { min: (2, 2), max: (4, 6) }));
+    assert_eq!(lines.rings().count(), 0);
+
+    let geometry = [9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15, 9, 4, 15, 26, 0, 4, 4, 0, 0, 3, 15];
+    let polygon = Geometry::decode(GeomType::POLYGON, &geometry, 2, InvalidRings::Reject).unwrap();
+    assert_eq!(polygon.geom_type(), GeomType::POLYGON);
+    assert_eq!(polygon.parts().count(), 1);
+    assert_eq!(polygon.rings().count(), 2);
+    assert_eq!(polygon.bbox(), Some(BoundingBox { min: (0, 0), max: (10, 10) }));
+    let empty = Geometry::decode(GeomType::POLYGON, &[], 2, InvalidRings::Repair).unwrap();
+    assert_eq!(empty.bbox(), None);
+}
+
+#[cfg(feature = "geo-types")]
+#[test]
+fn test_geo_types() {
+    let geometry = [9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15, 9, 4, 15, 26, 0, 4, 4, 0, 0, 3, 15];
+    let polygon = Geometry::decode(GeomType::POLYGON, &geometry, 2, InvalidRings::Reject).unwrap();
+    match geo_types::Geometry::from(polygon) {
+        geo_types::Geometry::Polygon(polygon) => {
+            assert_eq!(polygon.exterior().0.len(), 5);
+            assert_eq!(polygon.interiors().len(), 1);
+            assert_eq!(polygon.interiors()[0].0[1], geo_types::Coord { x: 2, y: 4 });
+        },
+        other => panic!("unexpected geometry: {:?}", other),
+    }
+}
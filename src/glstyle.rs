@@ -0,0 +1,610 @@
+//! Applies Mapbox GL (and MapLibre GL) styles while rendering.
+//!
+//! A style is a list of layers. Each style layer selects features by
+//! `source-layer`, `filter` and zoom range and defines how they are
+//! painted. The paint properties are translated to CSS, either inline
+//! per element or as a stylesheet with one class per style layer.
+//!
+//! Supported are `fill`, `line`, `circle` and `symbol` (text only)
+//! layers with colors, opacities, widths, dash arrays and radii.
+//! Properties may depend on the zoom level with `stops` or the
+//! `interpolate` and `step` expressions. Other properties and layer
+//! types are ignored. Features not selected by any layer are hidden.
+//!
+//! Like in GL renderers every style layer paints the features it
+//! selects separately and the style layers are drawn in the order of
+//! the style, regardless of the `sort_rank` of the features.
+//!
+//! GL styles are made for tiles of 512 pixels, so widths and sizes are
+//! halved for the 256 pixel tiles of this crate.
+
+use std::fmt::Write;
+
+use serde_json::{self, Map, Value as Json};
+
+use error::{Error, Result};
+use escape::ClassToken;
+use feature::Feature;
+use filter::Filter;
+use vector_tile::Tile_GeomType as GeomType;
+
+/// Converts GL pixels to pixels of a 256 pixel tile.
+const PIXEL_SCALE: f64 = 0.5;
+
+/// The kinds of style layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerType {
+    /// Fills polygons.
+    Fill,
+    /// Strokes lines and outlines of polygons.
+    Line,
+    /// Draws points as circles.
+    Circle,
+    /// Labels points.
+    Symbol,
+    /// Backgrounds, rasters, extrusions and other unsupported types.
+    Other,
+}
+
+/// A CSS property and its value.
+pub type Declaration = (&'static str, String);
+
+/// A layer of a style.
+#[derive(Debug, Clone)]
+pub struct StyleLayer {
+    id: String,
+    kind: LayerType,
+    source_layer: Option<String>,
+    filter: Option<Filter>,
+    min_zoom: f64,
+    max_zoom: f64,
+    paint: Map<String, Json>,
+    layout: Map<String, Json>,
+}
+
+/// A Mapbox GL style document.
+#[derive(Debug, Clone)]
+pub struct GlStyle {
+    layers: Vec<StyleLayer>,
+}
+
+/// How a style layer paints a feature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerStyle<'s> {
+    /// The position of the style layer, higher layers are drawn on top.
+    pub index: usize,
+    /// The id of the style layer.
+    pub id: &'s str,
+    /// The type of the style layer, symbol layers paint labels.
+    pub kind: LayerType,
+    /// CSS declarations for the path, circle or label.
+    pub declarations: Vec<Declaration>,
+}
+
+impl GlStyle {
+    /// Reads a style document.
+    ///
+    /// Hidden layers (`"visibility": "none"`) are dropped. Layers with
+    /// a filter that is not supported are left out as well, the
+    /// returned warnings tell which and why.
+    pub fn from_json(json: &str) -> Result<(GlStyle, Vec<Error>)> {
+        let document: Json = serde_json::from_str(json).map_err(Error::Json)?;
+        let layers = match document.get("layers") {
+            Some(Json::Array(layers)) => layers,
+            _ => return Err(Error::InvalidStyle("the style has no layers")),
+        };
+        let mut style = GlStyle { layers: Vec::new() };
+        let mut warnings = Vec::new();
+        for layer in layers {
+            let layer = match StyleLayer::from_json(layer) {
+                Ok(layer) => layer,
+                Err(e @ Error::StyleLayer { .. }) => {
+                    warnings.push(e);
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
+            if layer.layout.get("visibility").and_then(Json::as_str) != Some("none") {
+                style.layers.push(layer);
+            }
+        }
+        Ok((style, warnings))
+    }
+
+    /// The layers of the style in drawing order.
+    pub fn layers(&self) -> &[StyleLayer] {
+        &self.layers
+    }
+
+    /// The style layers that select the feature at the zoom level in
+    /// drawing order.
+    ///
+    /// Each layer paints its own copy of the feature. Symbol layers
+    /// without a label are left out. The list is empty if the feature
+    /// is not selected by any layer.
+    pub fn feature_styles(&self, feature: &Feature, zoom: f64) -> Vec<LayerStyle<'_>> {
+        let mut styles = Vec::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !layer.selects(feature, zoom) {
+                continue;
+            }
+            let paint = layer.declarations(zoom);
+            if paint.is_empty() {
+                continue;
+            }
+            let mut declarations = base_declarations(layer.kind);
+            for (property, value) in paint {
+                match declarations.iter_mut().find(|d| d.0 == property) {
+                    Some(declaration) => declaration.1 = value,
+                    None => declarations.push((property, value)),
+                }
+            }
+            styles.push(LayerStyle { index, id: &layer.id, kind: layer.kind, declarations });
+        }
+        styles
+    }
+
+    /// Creates a stylesheet for the zoom level.
+    ///
+    /// Each element is expected to carry the class `gl-{id}` of the
+    /// style layer that painted it, see `feature_styles`.
+    pub fn stylesheet(&self, zoom: f64) -> String {
+        let mut css = String::new();
+        css.push_str("path, circle { fill: none; stroke: none; }\n");
+        css.push_str("text { fill: none; font-family: sans-serif; text-anchor: middle; }\n");
+        for layer in self.layers.iter().filter(|layer| layer.visible_at(zoom)) {
+            let element = match layer.kind {
+                LayerType::Fill | LayerType::Line => "path",
+                LayerType::Circle => "circle",
+                LayerType::Symbol => "text",
+                LayerType::Other => continue,
+            };
+            let declarations = layer.declarations(zoom);
+            if declarations.is_empty() {
+                continue;
+            }
+            write!(css, "{}.gl-{} {{", element, ClassToken(&layer.id)).unwrap();
+            for (property, value) in declarations {
+                write!(css, " {}: {};", property, value).unwrap();
+            }
+            css.push_str(" }\n");
+        }
+        css
+    }
+}
+
+/// The CSS properties every element of a layer type needs.
+///
+/// SVG fills shapes black by default, GL only fills with fill layers.
+fn base_declarations(kind: LayerType) -> Vec<Declaration> {
+    match kind {
+        LayerType::Symbol => vec![
+            ("font-family", "sans-serif".to_owned()),
+            ("text-anchor", "middle".to_owned()),
+        ],
+        _ => vec![("fill", "none".to_owned()), ("stroke", "none".to_owned())],
+    }
+}
+
+impl StyleLayer {
+    fn from_json(json: &Json) -> Result<StyleLayer> {
+        let id = json.get("id").and_then(Json::as_str)
+            .ok_or(Error::InvalidStyle("a layer needs an id"))?;
+        let kind = match json.get("type").and_then(Json::as_str) {
+            Some("fill") => LayerType::Fill,
+            Some("line") => LayerType::Line,
+            Some("circle") => LayerType::Circle,
+            Some("symbol") => LayerType::Symbol,
+            Some(_) => LayerType::Other,
+            None => return Err(Error::InvalidStyle("a layer needs a type")),
+        };
+        let object = |key| match json.get(key) {
+            Some(Json::Object(map)) => Ok(map.clone()),
+            None => Ok(Map::new()),
+            Some(_) => Err(Error::InvalidStyle("paint and layout must be objects")),
+        };
+        Ok(StyleLayer {
+            id: id.to_owned(),
+            kind,
+            source_layer: json.get("source-layer").and_then(Json::as_str).map(str::to_owned),
+            filter: match json.get("filter") {
+                Some(filter) => Some(Filter::from_json(filter).map_err(|e| Error::StyleLayer {
+                    id: id.to_owned(),
+                    error: Box::new(e),
+                })?),
+                None => None,
+            },
+            min_zoom: json.get("minzoom").and_then(Json::as_f64).unwrap_or(0.0),
+            max_zoom: json.get("maxzoom").and_then(Json::as_f64).unwrap_or(24.0),
+            paint: object("paint")?,
+            layout: object("layout")?,
+        })
+    }
+
+    /// The id of the layer.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The type of the layer.
+    pub fn kind(&self) -> LayerType {
+        self.kind
+    }
+
+    fn visible_at(&self, zoom: f64) -> bool {
+        self.min_zoom <= zoom && zoom < self.max_zoom
+    }
+
+    /// Returns true if the layer paints the feature at the zoom level.
+    pub fn selects(&self, feature: &Feature, zoom: f64) -> bool {
+        let geom_type = feature.geom_type();
+        let compatible = match self.kind {
+            LayerType::Fill => geom_type == GeomType::POLYGON,
+            LayerType::Line => geom_type == GeomType::LINESTRING || geom_type == GeomType::POLYGON,
+            LayerType::Circle | LayerType::Symbol => geom_type == GeomType::POINT,
+            LayerType::Other => false,
+        };
+        compatible && self.visible_at(zoom)
+            && self.source_layer.as_ref().is_none_or(|name| name == feature.layer())
+            && self.filter.as_ref().is_none_or(|filter| filter.matches_feature(feature))
+    }
+
+    /// Translates the paint properties at the zoom level to CSS.
+    ///
+    /// Symbol layers without a `text-field` have no declarations.
+    pub fn declarations(&self, zoom: f64) -> Vec<Declaration> {
+        let mut css = Vec::new();
+        let color = |name: &str| self.paint.get(name).and_then(|v| evaluate(v, zoom))
+            .and_then(|v| v.as_str().and_then(css_value));
+        let number = |name: &str| self.paint.get(name).and_then(|v| evaluate(v, zoom))
+            .and_then(|v| v.as_f64());
+        let layout = |name: &str| self.layout.get(name).and_then(|v| evaluate(v, zoom))
+            .and_then(|v| v.as_str().and_then(css_value));
+        let pixels = |x: f64| format!("{}", x * PIXEL_SCALE);
+        match self.kind {
+            LayerType::Fill => {
+                css.push(("fill", color("fill-color").unwrap_or_else(|| "#000000".to_owned())));
+                if let Some(opacity) = number("fill-opacity") {
+                    css.push(("fill-opacity", opacity.to_string()));
+                }
+                if let Some(outline) = color("fill-outline-color") {
+                    css.push(("stroke", outline));
+                    css.push(("stroke-width", pixels(1.0)));
+                }
+            },
+            LayerType::Line => {
+                let width = number("line-width").unwrap_or(1.0);
+                css.push(("stroke", color("line-color").unwrap_or_else(|| "#000000".to_owned())));
+                css.push(("stroke-width", pixels(width)));
+                if let Some(opacity) = number("line-opacity") {
+                    css.push(("stroke-opacity", opacity.to_string()));
+                }
+                // Dashes are given in multiples of the line width.
+                let dashes = self.paint.get("line-dasharray").and_then(|v| evaluate(v, zoom));
+                if let Some(Json::Array(dashes)) = dashes {
+                    let dashes: Vec<String> = dashes.iter().filter_map(Json::as_f64)
+                        .map(|dash| pixels(dash * width)).collect();
+                    css.push(("stroke-dasharray", dashes.join(" ")));
+                }
+                if let Some(cap) = layout("line-cap") {
+                    css.push(("stroke-linecap", cap));
+                }
+                if let Some(join) = layout("line-join") {
+                    css.push(("stroke-linejoin", join));
+                }
+            },
+            LayerType::Circle => {
+                css.push(("fill", color("circle-color").unwrap_or_else(|| "#000000".to_owned())));
+                css.push(("r", pixels(number("circle-radius").unwrap_or(5.0))));
+                if let Some(opacity) = number("circle-opacity") {
+                    css.push(("fill-opacity", opacity.to_string()));
+                }
+                if let Some(stroke) = color("circle-stroke-color") {
+                    css.push(("stroke", stroke));
+                    css.push(("stroke-width", pixels(number("circle-stroke-width").unwrap_or(0.0))));
+                }
+            },
+            LayerType::Symbol if self.layout.contains_key("text-field") => {
+                css.push(("fill", color("text-color").unwrap_or_else(|| "#000000".to_owned())));
+                let size = self.layout.get("text-size").and_then(|v| evaluate(v, zoom))
+                    .and_then(|v| v.as_f64()).unwrap_or(16.0);
+                css.push(("font-size", format!("{}px", size * PIXEL_SCALE)));
+                if let Some(opacity) = number("text-opacity") {
+                    css.push(("fill-opacity", opacity.to_string()));
+                }
+            },
+            LayerType::Symbol | LayerType::Other => (),
+        }
+        css
+    }
+}
+
+/// Accepts values that can neither end a declaration nor leave the style.
+fn css_value(value: &str) -> Option<String> {
+    let safe = value.chars().all(|c| c.is_ascii_alphanumeric() || "#(),.% -".contains(c));
+    if safe && !value.is_empty() {
+        Some(value.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Evaluates a property value at the zoom level.
+///
+/// Constants are returned as they are. Zoom functions with `stops`
+/// and the expressions `["interpolate", ..., ["zoom"], ...]` and
+/// `["step", ["zoom"], ...]` are evaluated, other expressions are
+/// not supported.
+fn evaluate(value: &Json, zoom: f64) -> Option<Json> {
+    match *value {
+        Json::Object(ref function) => {
+            let stops = function.get("stops")?.as_array()?;
+            let stops: Vec<(f64, &Json)> = stops.iter().filter_map(|stop| match stop.as_array() {
+                Some(stop) if stop.len() == 2 => Some((stop[0].as_f64()?, &stop[1])),
+                _ => None,
+            }).collect();
+            let base = function.get("base").and_then(Json::as_f64).unwrap_or(1.0);
+            interpolate(&stops, base, zoom)
+        },
+        Json::Array(ref expression) => match expression.first().and_then(Json::as_str) {
+            Some("interpolate") if expression.len() >= 5 && is_zoom(&expression[2]) => {
+                let base = match expression[1].as_array().map(Vec::as_slice) {
+                    Some([Json::String(ref kind)]) if kind == "linear" => 1.0,
+                    Some([Json::String(ref kind), ref base]) if kind == "exponential" => base.as_f64()?,
+                    _ => return None,
+                };
+                let stops: Option<Vec<(f64, &Json)>> = expression[3..].chunks(2)
+                    .map(|pair| Some((pair[0].as_f64()?, pair.get(1)?))).collect();
+                interpolate(&stops?, base, zoom)
+            },
+            Some("step") if expression.len() >= 3 && is_zoom(&expression[1]) => {
+                let mut result = &expression[2];
+                for pair in expression[3..].chunks(2) {
+                    match (pair[0].as_f64(), pair.get(1)) {
+                        (Some(stop), Some(value)) if stop <= zoom => result = value,
+                        _ => break,
+                    }
+                }
+                Some(result.clone())
+            },
+            // Arrays are constant values, like dashes, unless they start with an operator.
+            Some(_) => None,
+            None => Some(value.clone()),
+        },
+        _ => Some(value.clone()),
+    }
+}
+
+fn is_zoom(input: &Json) -> bool {
+    input.as_array().map(Vec::as_slice) == Some(&[Json::String("zoom".to_owned())][..])
+}
+
+/// Interpolates numbers between the stops around the zoom level.
+///
+/// Other values change at the stops.
+fn interpolate(stops: &[(f64, &Json)], base: f64, zoom: f64) -> Option<Json> {
+    let after = stops.iter().position(|&(stop, _)| stop > zoom);
+    let (lower, upper) = match after {
+        Some(0) => return stops.first().map(|stop| stop.1.clone()),
+        Some(i) => (stops[i - 1], stops[i]),
+        None => return stops.last().map(|stop| stop.1.clone()),
+    };
+    match (lower.1.as_f64(), upper.1.as_f64()) {
+        (Some(a), Some(b)) => {
+            let range = upper.0 - lower.0;
+            let progress = zoom - lower.0;
+            let t = if base == 1.0 {
+                progress / range
+            } else {
+                (base.powf(progress) - 1.0) / (base.powf(range) - 1.0)
+            };
+            serde_json::Number::from_f64(a + (b - a) * t).map(Json::Number)
+        },
+        _ => Some(lower.1.clone()),
+    }
+}
+
+#[cfg(feature = "raster")]
+mod raster_style {
+    use super::{Declaration, GlStyle, LayerType};
+    use feature::Feature;
+    use raster::{Color, Style};
+
+    impl GlStyle {
+        /// The styles of a feature for the raster painter, ordered by the
+        /// index of the style layer.
+        ///
+        /// Only shapes are drawn, symbol layers are left out.
+        pub fn raster_styles(&self, feature: &Feature, zoom: f64) -> Vec<(u32, Style)> {
+            self.feature_styles(feature, zoom).into_iter()
+                .filter(|layer| layer.kind != LayerType::Symbol)
+                .filter_map(|layer| Some((layer.index as u32, raster_style(&layer.declarations)?)))
+                .collect()
+        }
+    }
+
+    /// Converts the CSS declarations of a shape, `None` if nothing is drawn.
+    fn raster_style(shape: &[Declaration]) -> Option<Style> {
+        let get = |property: &str| shape.iter()
+            .find(|d: &&Declaration| d.0 == property).map(|d| d.1.as_str());
+        let number = |property: &str| get(property).and_then(|v| v.parse::<f32>().ok());
+        let fill = get("fill").and_then(parse_color);
+        let stroke = get("stroke").and_then(parse_color);
+        let mut style = Style {
+            fill: fill.map(|c| c.0),
+            stroke: stroke.map(|c| c.0),
+            ..Style::default()
+        };
+        if let Some(width) = number("stroke-width") {
+            style.stroke_width = width;
+        }
+        // A stroke without width is not drawn, tiny-skia would draw a hairline.
+        if style.stroke_width <= 0.0 {
+            style.stroke = None;
+        }
+        if let Some(radius) = number("r") {
+            style.radius = radius;
+        }
+        if let Some(dashes) = get("stroke-dasharray") {
+            style.dashes = dashes.split(' ').filter_map(|d| d.parse().ok()).collect();
+        }
+        // The alpha of a color and the opacity property multiply.
+        style.fill_opacity = fill.map_or(1.0, |c| c.1) * number("fill-opacity").unwrap_or(1.0);
+        style.stroke_opacity = stroke.map_or(1.0, |c| c.1) * number("stroke-opacity").unwrap_or(1.0);
+        if style.fill.is_none() && style.stroke.is_none() {
+            return None;
+        }
+        Some(style)
+    }
+
+    /// Parses hex, `rgb()`, `rgba()` and a few named CSS colors.
+    pub fn parse_color(color: &str) -> Option<(Color, f32)> {
+        let hex = |s: &str| u8::from_str_radix(s, 16).ok();
+        let color = color.trim();
+        if let Some(digits) = color.strip_prefix('#') {
+            return match digits.len() {
+                3 | 4 => {
+                    let c: Vec<u8> = digits.chars()
+                        .map(|c| hex(&c.to_string()).map(|x| x * 17)).collect::<Option<_>>()?;
+                    Some(((c[0], c[1], c[2]), c.get(3).map_or(1.0, |&a| a as f32 / 255.0)))
+                },
+                6 | 8 => {
+                    let c: Vec<u8> = (0..digits.len()).step_by(2)
+                        .map(|i| digits.get(i..i + 2).and_then(hex)).collect::<Option<_>>()?;
+                    Some(((c[0], c[1], c[2]), c.get(3).map_or(1.0, |&a| a as f32 / 255.0)))
+                },
+                _ => None,
+            };
+        }
+        if let Some(args) = color.strip_prefix("rgba(").or_else(|| color.strip_prefix("rgb(")) {
+            let args: Vec<f32> = args.strip_suffix(')')?.split(',')
+                .map(|arg| arg.trim().parse().ok()).collect::<Option<_>>()?;
+            let channel = |x: f32| x.clamp(0.0, 255.0).round() as u8;
+            return match args.as_slice() {
+                [r, g, b] => Some(((channel(*r), channel(*g), channel(*b)), 1.0)),
+                [r, g, b, a] => Some(((channel(*r), channel(*g), channel(*b)), a.clamp(0.0, 1.0))),
+                _ => None,
+            };
+        }
+        let named = match color {
+            "black" => (0, 0, 0),
+            "white" => (255, 255, 255),
+            "red" => (255, 0, 0),
+            "green" => (0, 128, 0),
+            "blue" => (0, 0, 255),
+            "gray" | "grey" => (128, 128, 128),
+            "orange" => (255, 165, 0),
+            "yellow" => (255, 255, 0),
+            "purple" => (128, 0, 128),
+            "transparent" => return Some(((0, 0, 0), 0.0)),
+            _ => return None,
+        };
+        Some((named, 1.0))
+    }
+
+    #[test]
+    fn test_raster_circle() {
+        use raster::RasterPainter;
+        use tag::TagMap;
+        use vector_tile::{Tile_Feature, Tile_GeomType};
+
+        let (style, _) = GlStyle::from_json(r##"{"layers": [
+            {"id": "poi", "type": "circle", "paint": {"circle-color": "#f00", "circle-radius": 8,
+             "circle-opacity": 0.5, "circle-stroke-color": "#00f", "circle-stroke-width": 4}}
+        ]}"##).unwrap();
+        let mut raw_feature = Tile_Feature::new();
+        raw_feature.set_field_type(Tile_GeomType::POINT);
+        raw_feature.set_geometry(vec![9, 256, 256]);
+        let feature = Feature::new(&raw_feature, TagMap::new(), "pois", 2, 1.0).unwrap();
+        let styles = style.raster_styles(&feature, 12.0);
+        assert_eq!((styles[0].1.radius, styles[0].1.stroke_width), (4.0, 2.0));
+        // The opacity of the circle does not apply to its stroke.
+        assert_eq!((styles[0].1.fill_opacity, styles[0].1.stroke_opacity), (0.5, 1.0));
+
+        let mut painter = RasterPainter::with_styles(256, move |f| style.raster_styles(f, 12.0))
+            .unwrap();
+        feature.paint(&mut painter).unwrap();
+        let pixmap = painter.into_pixmap();
+        // The colors are premultiplied with the alpha.
+        let inside = pixmap.pixel(129, 128).unwrap();
+        assert_eq!((inside.red(), inside.blue(), inside.alpha()), (128, 0, 128));
+        let stroke = pixmap.pixel(131, 128).unwrap();
+        assert_eq!((stroke.blue(), stroke.alpha()), (255, 255));
+        assert_eq!(pixmap.pixel(134, 128).unwrap().alpha(), 0);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#5ac5e3"), Some(((0x5a, 0xc5, 0xe3), 1.0)));
+        assert_eq!(parse_color("#fff"), Some(((255, 255, 255), 1.0)));
+        assert_eq!(parse_color("rgba(10, 20, 30, 0.5)"), Some(((10, 20, 30), 0.5)));
+        assert_eq!(parse_color("grey"), Some(((128, 128, 128), 1.0)));
+        assert_eq!(parse_color("#12"), None);
+        assert_eq!(parse_color("hsl(0, 0%, 0%)"), None);
+    }
+}
+
+#[test]
+fn test_gl_style() {
+    use tag::{TagMap, Value};
+    use vector_tile::Tile_Feature;
+
+    let style = GlStyle::from_json(r##"{
+        "version": 8,
+        "layers": [
+            {"id": "background", "type": "background", "paint": {"background-color": "#fff"}},
+            {"id": "water", "type": "fill", "source-layer": "water",
+             "filter": ["==", "kind", "lake"], "paint": {"fill-color": "#5ac5e3"}},
+            {"id": "roads", "type": "line", "source-layer": "roads", "minzoom": 10,
+             "filter": ["in", "kind", "major_road", "highway"],
+             "paint": {"line-color": "orange", "line-dasharray": [2, 1],
+                       "line-width": {"stops": [[10, 1], [14, 5]]}}},
+            {"id": "highways", "type": "line", "source-layer": "roads",
+             "filter": ["==", "kind", "highway"],
+             "paint": {"line-color": "crimson;}", "line-width": ["step", ["zoom"], 2, 12, 4]}},
+            {"id": "hidden", "type": "fill", "layout": {"visibility": "none"}}
+        ]
+    }"##).unwrap().0;
+    assert_eq!(style.layers().len(), 4);
+
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_field_type(GeomType::LINESTRING);
+    let mut tags = TagMap::new();
+    tags.insert("kind", Value::String("highway"));
+    let feature = Feature::new(&raw_feature, tags, "roads", 2, 1.0).unwrap();
+    let ids = |feature: &Feature, zoom| style.feature_styles(feature, zoom).into_iter()
+        .map(|layer| (layer.index, layer.id)).collect::<Vec<_>>();
+    assert_eq!(ids(&feature, 9.0), [(3, "highways")]);
+    // Each style layer paints the feature with its own properties.
+    let styles = style.feature_styles(&feature, 12.0);
+    assert_eq!(styles.len(), 2);
+    assert_eq!((styles[0].id, styles[0].kind), ("roads", LayerType::Line));
+    assert_eq!(styles[0].declarations, [
+        ("fill", "none".to_owned()),
+        ("stroke", "orange".to_owned()),
+        ("stroke-width", "1.5".to_owned()),
+        ("stroke-dasharray", "3 1.5".to_owned()),
+    ]);
+    // The unsafe color is dropped and the default is used.
+    assert_eq!(styles[1].declarations, [
+        ("fill", "none".to_owned()),
+        ("stroke", "#000000".to_owned()),
+        ("stroke-width", "2".to_owned()),
+    ]);
+    let feature = Feature::new(&raw_feature, TagMap::new(), "roads", 2, 1.0).unwrap();
+    assert!(ids(&feature, 12.0).is_empty());
+
+    let css = style.stylesheet(9.0);
+    assert!(css.contains("path.gl-water { fill: #5ac5e3; }\n"));
+    assert!(!css.contains("gl-roads"));
+    assert!(GlStyle::from_json("{}").is_err());
+    // Layers with unsupported filters are skipped.
+    let (style, warnings) = GlStyle::from_json(r#"{"layers": [
+        {"id": "x", "type": "fill", "filter": ["match", ["get", "kind"], "lake", true, false]},
+        {"id": "y", "type": "fill"}
+    ]}"#).unwrap();
+    assert_eq!(style.layers().iter().map(StyleLayer::id).collect::<Vec<_>>(), ["y"]);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].to_string().starts_with("style layer \"x\": filter: "));
+    assert!(GlStyle::from_json(r#"{"layers": [{"type": "fill"}]}"#).is_err());
+}
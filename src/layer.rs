@@ -1,12 +1,20 @@
 //! Layers group features of similiar type and use together.
 //!
 //! Layers are a storage unit in MVT files.
-use protobuf::{ProtobufResult, ProtobufError};
+//!
+//! Each layer declares the version of the specification it follows.
+//! Version 1 did not define the winding order of polygon rings, so
+//! such polygons are filled with the even-odd rule. Version 2 fixes
+//! the winding order. Other versions are rejected.
+use std::collections::HashSet;
+
+use error::{Error, Result};
 
 use feature::Feature;
-use storage::Storage;
+use options::Options;
+use painter::Painter;
 use tag::{TagMap, Value};
-use vector_tile::Tile_Layer;
+use vector_tile::{Tile_Feature, Tile_Layer};
 
 /// Contains a layer and its scale.
 #[derive(Debug)]
@@ -25,39 +33,384 @@ impl<'l> Layer<'l> {
         }
     }
 
+    /// Returns the name of the layer.
+    pub fn name(&self) -> &'l str {
+        self.inner.get_name()
+    }
+
+    /// Returns the version of the specification the layer follows.
+    ///
+    /// Layers without a version are version 1.
+    pub fn version(&self) -> u32 {
+        self.inner.get_version()
+    }
+
+    /// Checks that the layer can be decoded.
+    ///
+    /// Fails for unsupported versions and layers with an extent of zero.
+    pub fn check(&self) -> Result<()> {
+        match self.version() {
+            1 | 2 => (),
+            version => return Err(Error::UnsupportedVersion {
+                layer: self.name().to_owned(),
+                version,
+            }),
+        }
+        if self.extent() == 0 {
+            return Err(Error::ZeroExtent(self.name().to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Returns the size of the tile in tile coordinates.
+    ///
+    /// Use it together with a `TileAddress` to project coordinates
+    /// decoded by a `Cursor` with scale 1.
+    pub fn extent(&self) -> u32 {
+        self.inner.get_extent()
+    }
+
     /// Decodes the tags of a feature using the layers dictionary.
     pub fn get_tags(&self, tags: &[u32])
-            -> ProtobufResult<TagMap> {
-        if tags.len() % 2 != 0 {
-            return Err(ProtobufError::WireError(
-                "mvt: A tag list must be an even number of integers.".to_owned()));
+            -> Result<TagMap<'_, '_>> {
+        if !tags.len().is_multiple_of(2) {
+            return Err(Error::OddTagCount(tags.len()));
         }
         let mut map = TagMap::new();
         let keys = self.inner.get_keys();
         let values = self.inner.get_values();
         for i in 0..(tags.len() / 2) {
-            let k = tags[i * 2] as usize;
-            let v = tags[i * 2 + 1] as usize;
-            if k >= keys.len() || v >= values.len() {
-                return Err(ProtobufError::WireError(
-                    "mvt: There is no such tag key/value.".to_owned()));
-            }
-            map.insert(keys[k].as_str(), Value::from_tile_value(&values[v])?);
+            let k = tags[i * 2];
+            let v = tags[i * 2 + 1];
+            let key = keys.get(k as usize).ok_or(Error::TagKeyOutOfRange(k))?;
+            let value = values.get(v as usize).ok_or(Error::TagValueOutOfRange(v))?;
+            map.insert(key.as_str(), Value::from_tile_value(value)?);
         }
         Ok(map)
     }
 
-    /// Paints all features in the tile to the given storage.
-    pub fn paint(&mut self, storage: &mut Storage) -> ProtobufResult<()> {
-        for raw_feature in self.inner.get_features() {
-            let mut feature = Feature::new(
-                raw_feature,
-                self.get_tags(raw_feature.get_tags())?,
-                self.inner.get_name(),
-                self.scale)?;
-            let mut rank = storage.select(feature.sort_rank);
-            feature.paint(&mut rank)?;
+    /// Paints all features in the layer with the given painter.
+    ///
+    /// Errors are reported with the layer name and index of the feature.
+    /// In lenient mode invalid features and layers with an extent of zero
+    /// are skipped and their errors are added to `warnings` instead.
+    /// Layers with an unknown version are skipped the same way if
+    /// `options.skip_unknown_versions` is set.
+    /// Features not matching `options.filter` are never painted, neither
+    /// are layers left out by `options.include_layers` and
+    /// `options.exclude_layers`.
+    pub fn paint<P: Painter + ?Sized>(&mut self, painter: &mut P, options: &Options,
+                                      warnings: &mut Vec<Error>)
+            -> Result<()> {
+        if !options.includes_layer(self.inner.get_name()) {
+            return Ok(());
+        }
+        if let Err(e) = self.check() {
+            let skip = match e {
+                Error::UnsupportedVersion { .. } => options.skip_unknown_versions,
+                Error::ZeroExtent(..) => options.lenient,
+                _ => false,
+            };
+            if !skip {
+                return Err(e);
+            }
+            warnings.push(e);
+            return Ok(());
+        }
+        let name = self.inner.get_name();
+        let mut ids = HashSet::new();
+        for (index, raw_feature) in self.inner.get_features().iter().enumerate() {
+            match self.paint_feature(raw_feature, painter, options) {
+                Ok(Some(id)) if options.report_duplicate_ids && !ids.insert(id) =>
+                    warnings.push(Error::DuplicateId(id).in_feature(name, index)),
+                Ok(_) => (),
+                Err(e) => {
+                    let e = e.in_feature(name, index);
+                    if options.lenient {
+                        warnings.push(e);
+                    } else {
+                        return Err(e);
+                    }
+                },
+            }
         }
         Ok(())
     }
+
+    /// Paints a feature and returns its id.
+    fn paint_feature<P: Painter + ?Sized>(&self, raw_feature: &Tile_Feature, painter: &mut P,
+                                          options: &Options) -> Result<Option<u64>> {
+        let mut feature = Feature::new(
+            raw_feature,
+            self.get_tags(raw_feature.get_tags())?,
+            self.inner.get_name(),
+            self.version(),
+            self.scale)?;
+        if let Some(ref key) = options.id_tag {
+            feature.use_id_tag(key);
+        }
+        feature.layer_rank = options.layer_rank(feature.layer());
+        if let Some(&sort_rank) = options.default_sort_ranks.get(feature.layer()) {
+            feature.use_default_sort_rank(sort_rank);
+        }
+        if options.filter.as_ref().is_some_and(|filter| !filter.matches_feature(&feature)) {
+            return Ok(None);
+        }
+        feature.paint(painter)?;
+        Ok(feature.id())
+    }
+}
+
+#[test]
+fn test_paint_reports_feature() {
+    use protobuf::RepeatedField;
+    use svg::SvgPainter;
+    use vector_tile::Tile_GeomType::LINESTRING;
+    use vector_tile::Tile_Value;
+
+    let mut kind = Tile_Value::new();
+    kind.set_string_value("path".to_owned());
+    let mut valid = Tile_Feature::new();
+    valid.set_field_type(LINESTRING);
+    valid.set_tags(vec![0, 0]);
+    valid.set_geometry(vec![9, 0, 0, 10, 4, 4]);
+    let mut closed = valid.clone();
+    closed.set_geometry(vec![9, 0, 0, 10, 4, 4, 15]);
+    let mut raw_layer = Tile_Layer::new();
+    raw_layer.set_name("roads".to_owned());
+    raw_layer.set_keys(RepeatedField::from_vec(vec!["kind".to_owned()]));
+    raw_layer.set_values(RepeatedField::from_vec(vec![kind]));
+    raw_layer.set_features(RepeatedField::from_vec(vec![valid, closed]));
+
+    let mut painter = SvgPainter::new();
+    let mut warnings = Vec::new();
+    match Layer::new(&raw_layer).paint(&mut painter, &Options::default(), &mut warnings) {
+        Err(Error::Feature { ref layer, index: 1, ref error }) if layer == "roads" => {
+            match **error {
+                Error::UnexpectedCommand { command: 7, geom_type: LINESTRING } => (),
+                ref e => panic!("unexpected error: {}", e),
+            }
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(warnings.is_empty());
+    assert!(Layer::new(&raw_layer).get_tags(&[0, 1]).is_err());
+}
+
+#[test]
+fn test_paint_lenient() {
+    use protobuf::RepeatedField;
+    use svg::SvgPainter;
+    use vector_tile::Tile_GeomType::LINESTRING;
+    use vector_tile::Tile_Value;
+
+    let mut kind = Tile_Value::new();
+    kind.set_string_value("path".to_owned());
+    let mut untagged = Tile_Feature::new();
+    untagged.set_field_type(LINESTRING);
+    untagged.set_geometry(vec![9, 0, 0, 10, 4, 4]);
+    let mut closed = untagged.clone();
+    closed.set_tags(vec![0, 0]);
+    closed.set_geometry(vec![9, 0, 0, 10, 4, 4, 15]);
+    let mut valid = closed.clone();
+    valid.set_geometry(vec![9, 0, 0, 10, 4, 4]);
+    let mut raw_layer = Tile_Layer::new();
+    raw_layer.set_name("roads".to_owned());
+    raw_layer.set_extent(256);
+    raw_layer.set_keys(RepeatedField::from_vec(vec!["kind".to_owned()]));
+    raw_layer.set_values(RepeatedField::from_vec(vec![kind]));
+    raw_layer.set_features(RepeatedField::from_vec(vec![untagged, closed, valid]));
+
+    let mut painter = SvgPainter::new();
+    let mut warnings = Vec::new();
+    let options = Options { lenient: true, ..Options::default() };
+    Layer::new(&raw_layer).paint(&mut painter, &options, &mut warnings).unwrap();
+    assert_eq!(String::from(painter).as_str(),
+        "<path class=\"layer-roads kind-unknown min-zoom-0\" d=\"M 0 0 L 2 2 \"></path>\n\
+         <path class=\"layer-roads kind-path min-zoom-0\" d=\"M 0 0 L 2 2 \"></path>\n");
+    assert_eq!(warnings.len(), 1);
+    match warnings[0] {
+        Error::Feature { index: 1, .. } => (),
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_layer_versions() {
+    use protobuf::RepeatedField;
+    use svg::SvgPainter;
+    use vector_tile::Tile_GeomType::POLYGON;
+    use vector_tile::Tile_Value;
+
+    let mut kind = Tile_Value::new();
+    kind.set_string_value("lake".to_owned());
+    let mut polygon = Tile_Feature::new();
+    polygon.set_field_type(POLYGON);
+    polygon.set_tags(vec![0, 0]);
+    polygon.set_geometry(vec![9, 0, 0, 26, 8, 0, 0, 8, 7, 0, 15]);
+    let mut raw_layer = Tile_Layer::new();
+    raw_layer.set_name("water".to_owned());
+    raw_layer.set_extent(256);
+    raw_layer.set_keys(RepeatedField::from_vec(vec!["kind".to_owned()]));
+    raw_layer.set_values(RepeatedField::from_vec(vec![kind]));
+    raw_layer.set_features(RepeatedField::from_vec(vec![polygon]));
+    let paint = |raw_layer: &Tile_Layer, options: &Options| {
+        let mut painter = SvgPainter::new();
+        let mut warnings = Vec::new();
+        Layer::new(raw_layer).paint(&mut painter, options, &mut warnings)
+            .map(|()| (String::from(painter), warnings.len()))
+    };
+
+    // Layers without a version field are version 1.
+    assert_eq!(Layer::new(&raw_layer).version(), 1);
+    assert_eq!(paint(&raw_layer, &Options::default()).unwrap(),
+        ("<path class=\"layer-water kind-lake min-zoom-0\" fill-rule=\"evenodd\" \
+          d=\"M 0 0 L 4 0 L 4 4 L 0 4 Z \"></path>\n".to_owned(), 0));
+    raw_layer.set_version(2);
+    assert_eq!(paint(&raw_layer, &Options::default()).unwrap(),
+        ("<path class=\"layer-water kind-lake min-zoom-0\" \
+          d=\"M 0 0 L 4 0 L 4 4 L 0 4 Z \"></path>\n".to_owned(), 0));
+
+    raw_layer.set_version(3);
+    match paint(&raw_layer, &Options::default()) {
+        Err(Error::UnsupportedVersion { version: 3, .. }) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let skip = Options { skip_unknown_versions: true, ..Options::default() };
+    assert_eq!(paint(&raw_layer, &skip).unwrap(), (String::new(), 1));
+    raw_layer.set_version(2);
+    raw_layer.set_extent(0);
+    assert!(paint(&raw_layer, &skip).is_err());
+    let lenient = Options { lenient: true, ..Options::default() };
+    assert_eq!(paint(&raw_layer, &lenient).unwrap(), (String::new(), 1));
+}
+
+#[test]
+fn test_feature_ids() {
+    use protobuf::RepeatedField;
+    use svg::SvgPainter;
+    use vector_tile::Tile_GeomType::POINT;
+    use vector_tile::Tile_Value;
+
+    let mut kind = Tile_Value::new();
+    kind.set_string_value("peak".to_owned());
+    let mut id = Tile_Value::new();
+    id.set_uint_value(7);
+    let mut first = Tile_Feature::new();
+    first.set_field_type(POINT);
+    first.set_id(7);
+    first.set_tags(vec![0, 0]);
+    first.set_geometry(vec![9, 2, 2]);
+    let mut tagged = first.clone();
+    tagged.clear_id();
+    tagged.set_tags(vec![0, 0, 1, 1]);
+    let mut raw_layer = Tile_Layer::new();
+    raw_layer.set_name("pois".to_owned());
+    raw_layer.set_version(2);
+    raw_layer.set_extent(256);
+    raw_layer.set_keys(RepeatedField::from_vec(vec!["kind".to_owned(), "id".to_owned()]));
+    raw_layer.set_values(RepeatedField::from_vec(vec![kind, id]));
+    raw_layer.set_features(RepeatedField::from_vec(vec![first, tagged]));
+    let paint = |options: &Options| {
+        let mut painter = SvgPainter::new();
+        let mut warnings = Vec::new();
+        Layer::new(&raw_layer).paint(&mut painter, options, &mut warnings).unwrap();
+        (String::from(painter).matches("data-id=\"7\"").count(), warnings)
+    };
+
+    // Only the id field is used by default.
+    let (ids, warnings) = paint(&Options::default());
+    assert_eq!(ids, 1);
+    assert!(warnings.is_empty());
+    let options = Options {
+        id_tag: Some("id".to_owned()),
+        ..Options::default()
+    };
+    assert_eq!(paint(&options).0, 2);
+    let options = Options { report_duplicate_ids: true, ..options };
+    let (ids, warnings) = paint(&options);
+    assert_eq!(ids, 2);
+    assert_eq!(warnings.len(), 1);
+    match warnings[0] {
+        Error::Feature { index: 1, ref error, .. } => match **error {
+            Error::DuplicateId(7) => (),
+            ref e => panic!("unexpected error: {}", e),
+        },
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_paint_filter() {
+    use encoder::LayerBuilder;
+    use filter::Filter;
+    use geometry::Geometry;
+    use svg::SvgPainter;
+
+    let line = Geometry::LineString(vec![(0, 0), (4, 4)]);
+    let mut builder = LayerBuilder::new("roads", 4096);
+    builder.add_feature(None, &line, &[("kind", Value::String("path"))]).unwrap();
+    builder.add_feature(None, &line, &[("kind", Value::String("highway"))]).unwrap();
+    let mut raw_layer = builder.build();
+    // A ClosePath is not allowed in a linestring.
+    raw_layer.mut_features()[1].mut_geometry().push(15);
+
+    // The invalid highway is filtered before it is painted.
+    let options = Options {
+        filter: Some(Filter::parse("kind == path").unwrap()),
+        ..Options::default()
+    };
+    let mut painter = SvgPainter::new();
+    let mut warnings = Vec::new();
+    Layer::new(&raw_layer).paint(&mut painter, &options, &mut warnings).unwrap();
+    assert_eq!(String::from(painter).matches("<path").count(), 1);
+
+    let options = Options {
+        filter: Some(Filter::parse("kind != path").unwrap()),
+        ..Options::default()
+    };
+    assert!(Layer::new(&raw_layer).paint(&mut SvgPainter::new(), &options, &mut warnings).is_err());
+}
+
+#[test]
+fn test_paint_layer_options() {
+    use encoder::LayerBuilder;
+    use geometry::Geometry;
+    use svg::SvgPainter;
+
+    let raw_layer = |name: &str, sort_rank: Option<u64>| {
+        let mut tags = vec![("kind", Value::String("x"))];
+        tags.extend(sort_rank.map(|rank| ("sort_rank", Value::Uint64(rank))));
+        let mut builder = LayerBuilder::new(name, 4096);
+        builder.add_feature(None, &Geometry::Point((1, 1)), &tags).unwrap();
+        builder.build()
+    };
+    let layers = [raw_layer("pois", None), raw_layer("roads", Some(300)), raw_layer("water", Some(100))];
+    let paint = |options: &Options| {
+        let mut painter = SvgPainter::new();
+        for raw_layer in &layers {
+            Layer::new(raw_layer).paint(&mut painter, options, &mut Vec::new()).unwrap();
+        }
+        let svg = String::from(painter);
+        svg.lines().filter_map(|line| line.split("layer-").nth(1)?.split(' ').next())
+            .map(str::to_owned).collect::<Vec<_>>()
+    };
+
+    assert_eq!(paint(&Options::default()), ["water", "roads", "pois"]);
+    let mut options = Options {
+        include_layers: Some(vec!["pois".to_owned(), "roads".to_owned()]),
+        ..Options::default()
+    };
+    assert_eq!(paint(&options), ["roads", "pois"]);
+    options.exclude_layers = vec!["roads".to_owned()];
+    assert_eq!(paint(&options), ["pois"]);
+    let mut options = Options {
+        layer_order: Some(vec!["pois".to_owned(), "roads".to_owned()]),
+        ..Options::default()
+    };
+    assert_eq!(paint(&options), ["pois", "roads", "water"]);
+    options.layer_order = None;
+    options.default_sort_ranks.insert("pois".to_owned(), 200);
+    options.default_sort_ranks.insert("water".to_owned(), 400);
+    assert_eq!(paint(&options), ["water", "pois", "roads"]);
 }
@@ -2,75 +2,235 @@
 //! contents as a Scalable Vector Graphic (.svg) fragment.
 //!
 //! Provides a single function `process(r: Read)` to convert
-//! files.
+//! files. Gzip and zlib compressed files are decompressed.
 //!
-//! Intended to be run in the browser.
+//! Intended to be run in the browser. Native builds provide
+//! a command line interface, see `colorful-map --help`.
 
 #![deny(missing_docs)]
-// Exported functions are passed to the asm.js linker.
-#![feature(link_args)]
 
+extern crate flate2;
+#[cfg(feature = "geo-types")]
+extern crate geo_types;
 extern crate protobuf;
+#[cfg(feature = "mbtiles")]
+extern crate rusqlite;
+extern crate serde_json;
+#[cfg(feature = "raster")]
+extern crate tiny_skia;
+#[cfg(feature = "toml")]
+extern crate toml;
 
+use std::borrow::Cow;
 use std::ffi::CString;
 use std::io::Read;
 use std::os::raw::c_char;
 use std::slice;
 
-use protobuf::ProtobufResult;
-
-use storage::Storage;
+use error::{Error, Result};
+use options::Options;
 use layer::Layer;
+use painter::Painter;
+use svg::SvgPainter;
 use vector_tile::Tile;
 
+#[cfg(not(target_os = "emscripten"))]
+mod batch;
+#[cfg(not(target_os = "emscripten"))]
+mod cli;
+pub mod classes;
+pub mod compression;
 pub mod cursor;
+pub mod document;
+pub mod encoder;
+pub mod error;
+pub mod escape;
 pub mod feature;
+pub mod filter;
+pub mod geojson;
+pub mod geometry;
+pub mod glstyle;
 pub mod storage;
+pub mod svg;
 pub mod tag;
+pub mod tiler;
+pub mod validate;
 pub mod layer;
-#[allow(missing_docs)]
+#[cfg(feature = "mbtiles")]
+pub mod mbtiles;
+pub mod options;
+pub mod painter;
+pub mod pmtiles;
+pub mod projection;
+#[cfg(feature = "raster")]
+pub mod raster;
+#[allow(missing_docs, bare_trait_objects, deprecated, renamed_and_removed_lints,
+        static_mut_refs, for_loops_over_fallibles, clippy::all)]
 pub mod vector_tile;
 
+/// A rendered tile.
+#[derive(Debug)]
+pub struct Rendered {
+    /// The SVG fragment.
+    pub svg: String,
+    /// Problems that did not stop rendering: skipped features and
+    /// layers and, if requested, duplicate ids.
+    pub warnings: Vec<Error>,
+}
+
 /// Reads a Vector File and produces an SVG fragment for a tile.
-pub fn process<R: Read>(mut r: R) -> ProtobufResult<String> {
-    let tile: Tile = protobuf::parse_from_reader(&mut r)?;
-    let mut storage = Storage::new();
+pub fn process<R: Read>(r: R) -> Result<String> {
+    process_with_options(r, &Options::default()).map(|rendered| rendered.svg)
+}
+
+/// Reads a Vector File and produces an SVG fragment using the given options.
+pub fn process_with_options<R: Read>(r: R, options: &Options) -> Result<Rendered> {
+    let mut painter = SvgPainter::new()
+        .with_class_mapping(options.classes.clone())
+        .with_data_tags(options.data_tags.clone());
+    let warnings = paint(r, options, &mut painter)?;
+    Ok(Rendered {
+        svg: String::from(painter),
+        warnings,
+    })
+}
+
+/// Reads a Vector File and paints all layers with the given painter.
+///
+/// Returns the problems that did not stop painting, see `Rendered`.
+pub fn paint<R: Read, P: Painter + ?Sized>(mut r: R, options: &Options, painter: &mut P)
+        -> Result<Vec<Error>> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data).map_err(Error::Io)?;
+    let data = if options.decompress {
+        compression::decompress(&data)?
+    } else {
+        Cow::Borrowed(&data[..])
+    };
+    let tile: Tile = protobuf::parse_from_bytes(&data)?;
+    let mut warnings = Vec::new();
 
     for raw_layer in tile.get_layers() {
         let mut layer = Layer::new(raw_layer);
-        layer.paint(&mut storage)?;
+        layer.paint(painter, options, &mut warnings)?;
     }
-    Ok(String::from(storage))
+    Ok(warnings)
 }
 
-// Only the functions listed here are callable from JS.
-#[cfg_attr(target_arch="asmjs",
-           link_args="-s EXPORTED_FUNCTIONS=['_process_web','_free_cstring_web']")]
-extern {}
-
 /// Process a map tile.
 ///
 /// Takes an array as a pointer and a length.
 /// Returns a C string.
+///
+/// Invalid features, layers with an extent of zero and layers with
+/// unknown versions are skipped.
+/// If the tile can't be read at all
+/// an empty fragment is returned. Problems are logged to the console.
+///
+/// # Safety
+///
+/// `p` must point to `len` readable bytes. The returned string
+/// must be freed with `free_cstring_web`.
 #[no_mangle]
-pub extern fn process_web(p: *const u8, len: usize) -> *const c_char {
+pub unsafe extern "C" fn process_web(p: *const u8, len: usize) -> *const c_char {
     let input = unsafe {
         slice::from_raw_parts(p, len)
     };
-    let output = process(input).expect("input file is valid");
+    let options = Options {
+        lenient: true,
+        skip_unknown_versions: true,
+        // The tiles of the web map store ids in a tag.
+        id_tag: Some("id".to_owned()),
+        ..Options::default()
+    };
+    let output = match process_with_options(input, &options) {
+        Ok(rendered) => {
+            for warning in rendered.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            rendered.svg
+        },
+        Err(e) => {
+            eprintln!("failed to render tile: {}", e);
+            String::new()
+        },
+    };
     CString::new(output).expect("string contains no internal NULL").into_raw()
 }
 
 /// CString must not be just freed but the drop function must run according to docs.
 ///
 /// Note: The Drop impl writes 0 in the byte of string and makes it unusable.
+///
+/// # Safety
+///
+/// `p` must be null or a string returned by `process_web`.
 #[no_mangle]
-pub extern fn free_cstring_web(p: *mut c_char) {
+pub unsafe extern "C" fn free_cstring_web(p: *mut c_char) {
     unsafe {
         if p.is_null() { return }
-        CString::from_raw(p)
+        drop(CString::from_raw(p))
     };
 }
 
+#[test]
+fn test_process_escapes_malicious_tile() {
+    use protobuf::{Message, RepeatedField};
+    use vector_tile::{Tile_Feature, Tile_GeomType, Tile_Layer, Tile_Value};
+
+    fn string_value(s: &str) -> Tile_Value {
+        let mut value = Tile_Value::new();
+        value.set_string_value(s.to_owned());
+        value
+    }
+
+    let mut feature = Tile_Feature::new();
+    feature.set_field_type(Tile_GeomType::POINT);
+    feature.set_geometry(vec![9, 50, 34]);
+    feature.set_tags(vec![0, 0, 1, 1]);
+    let mut layer = Tile_Layer::new();
+    layer.set_version(2);
+    layer.set_name("x\"><script>alert(1)</script>".to_owned());
+    layer.set_keys(RepeatedField::from_vec(vec!["kind".to_owned(), "name".to_owned()]));
+    layer.set_values(RepeatedField::from_vec(vec![
+        string_value("city\" onload=\"alert(2)"),
+        string_value("</text><script>alert(3)</script>"),
+    ]));
+    layer.set_features(RepeatedField::from_vec(vec![feature]));
+    let mut tile = Tile::new();
+    tile.set_layers(RepeatedField::from_vec(vec![layer]));
+    let bytes = tile.write_to_bytes().unwrap();
+
+    let output = process(bytes.as_slice()).unwrap();
+    assert!(!output.contains("<script"));
+    assert!(!output.contains("\" onload"));
+    assert_eq!(output.as_str(),
+        "<circle class=\"layer-x___script_alert_1___script_ kind-city__onload__alert_2_ min-zoom-0\" \
+         cx=\"1.5625\" cy=\"1.0625\" r=\"1\"></circle>\n\
+         <text class=\"layer-x___script_alert_1___script_ kind-city__onload__alert_2_ min-zoom-0\" \
+         x=\"1.5625\" y=\"1.0625\">&lt;/text&gt;&lt;script&gt;alert(3)&lt;/script&gt;</text>\n");
+}
+
+#[test]
+fn test_process_compressed() {
+    let gzip: &[u8] = include_bytes!("../web/tile.mvt");
+    assert!(process(gzip).unwrap().contains("<path"));
+    let options = Options { decompress: false, ..Options::default() };
+    match process_with_options(gzip, &options) {
+        Err(Error::Protobuf(_)) => (),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    match process(&gzip[..gzip.len() / 2]) {
+        Err(Error::Decompression(_)) => (),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
 /// asm.js expects a main function.
+#[cfg(target_os = "emscripten")]
 fn main() {}
+
+#[cfg(not(target_os = "emscripten"))]
+fn main() {
+    cli::main()
+}
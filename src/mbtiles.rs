@@ -0,0 +1,203 @@
+//! Reads tiles from MBTiles archives.
+//!
+//! MBTiles stores tiles in an SQLite database. Rows are numbered
+//! from the south (TMS scheme) and the tile data is usually gzip
+//! compressed. The reader hides both details: tiles are addressed
+//! like in the web map and returned as plain MVT data ready for
+//! `process`.
+//!
+//! Archives can also be created, for example from the tiles
+//! produced by `tiler`.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use compression;
+use error::{Error, Result};
+use projection::TileAddress;
+
+/// An open MBTiles archive.
+#[derive(Debug)]
+pub struct MbTiles {
+    connection: Connection,
+}
+
+impl MbTiles {
+    /// Opens an archive for reading.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MbTiles> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(MbTiles { connection })
+    }
+
+    /// Creates an archive or opens an existing one for writing.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<MbTiles> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS metadata (name text, value text);
+             CREATE UNIQUE INDEX IF NOT EXISTS name ON metadata (name);
+             CREATE TABLE IF NOT EXISTS tiles (zoom_level integer, tile_column integer,
+                                               tile_row integer, tile_data blob);
+             CREATE UNIQUE INDEX IF NOT EXISTS tile_index
+                 ON tiles (zoom_level, tile_column, tile_row);")?;
+        Ok(MbTiles { connection })
+    }
+
+    /// Sets an entry of the metadata table.
+    pub fn set_metadata(&self, name: &str, value: &str) -> Result<()> {
+        self.connection.execute("INSERT OR REPLACE INTO metadata VALUES (?1, ?2)", [name, value])?;
+        Ok(())
+    }
+
+    /// Stores tiles gzip compressed, replacing existing tiles.
+    pub fn insert_tiles(&mut self, tiles: &[(TileAddress, Vec<u8>)]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT OR REPLACE INTO tiles VALUES (?1, ?2, ?3, ?4)")?;
+            for &(address, ref data) in tiles {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.try_finish())
+                    .map_err(Error::Io)?;
+                let row = flip_row(address.z, address.y);
+                statement.execute(params![address.z, address.x, row, encoder.get_ref()])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the entries of the metadata table.
+    pub fn metadata(&self) -> Result<BTreeMap<String, String>> {
+        let mut statement = self.connection.prepare("SELECT name, value FROM metadata")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut metadata = BTreeMap::new();
+        for row in rows {
+            let (name, value) = row?;
+            metadata.insert(name, value);
+        }
+        Ok(metadata)
+    }
+
+    /// Returns the lowest and highest zoom level with tiles.
+    ///
+    /// Returns `None` if the archive contains no tiles.
+    pub fn zoom_range(&self) -> Result<Option<(u8, u8)>> {
+        Ok(self.connection.query_row(
+            "SELECT min(zoom_level), max(zoom_level) FROM tiles \
+             WHERE zoom_level BETWEEN 0 AND 31", [],
+            |row| Ok(match (row.get(0)?, row.get(1)?) {
+                (Some(min), Some(max)) => Some((min, max)),
+                _ => None,
+            }))?)
+    }
+
+    /// Returns the addresses of all tiles in the archive.
+    ///
+    /// Rows which do not address a valid tile are skipped.
+    pub fn addresses(&self) -> Result<Vec<TileAddress>> {
+        let mut statement = self.connection.prepare(
+            "SELECT zoom_level, tile_column, tile_row FROM tiles \
+             ORDER BY zoom_level, tile_column, tile_row DESC")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        let mut addresses = Vec::new();
+        for row in rows {
+            // Read as i64 so negative and huge numbers are skipped as well.
+            let (z, x, row): (i64, i64, i64) = row?;
+            let (z, x, row) = match (u8::try_from(z), u32::try_from(x), u32::try_from(row)) {
+                (Ok(z), Ok(x), Ok(row)) if z <= 31 && (row as u64) < 1 << z => (z, x, row),
+                _ => continue,
+            };
+            if let Some(address) = TileAddress::new(z, x, flip_row(z, row)) {
+                addresses.push(address);
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// Returns the data of a tile as stored in the archive.
+    pub fn get_raw(&self, address: TileAddress) -> Result<Option<Vec<u8>>> {
+        if !address.is_valid() {
+            return Ok(None);
+        }
+        Ok(self.connection.query_row(
+            "SELECT tile_data FROM tiles \
+             WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            [address.z as u32, address.x, flip_row(address.z, address.y)],
+            |row| row.get(0)).optional()?)
+    }
+
+    /// Returns the MVT data of a tile.
+    ///
+    /// Gzip and zlib compressed tiles are decompressed.
+    pub fn get(&self, address: TileAddress) -> Result<Option<Vec<u8>>> {
+        match self.get_raw(address)? {
+            Some(data) => Ok(Some(compression::decompress(&data)?.into_owned())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Converts between rows counted from the north and from the south.
+fn flip_row(z: u8, row: u32) -> u32 {
+    ((1u64 << z) - 1 - row as u64) as u32
+}
+
+#[test]
+fn test_mbtiles() {
+    use std::env;
+    use std::fs;
+
+    let path = env::temp_dir().join(format!("colorful-map-{}.mbtiles", ::std::process::id()));
+    {
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch(
+            "CREATE TABLE metadata (name text, value text);
+             CREATE TABLE tiles (zoom_level integer, tile_column integer,
+                                 tile_row integer, tile_data blob);
+             INSERT INTO metadata VALUES ('name', 'test'), ('format', 'pbf');").unwrap();
+        // The web map example tile is gzip compressed.
+        let tile: &[u8] = include_bytes!("../web/tile.mvt");
+        connection.execute("INSERT INTO tiles VALUES (12, 2138, 2707, ?1), (10, 1, 1, x'00')",
+                           [tile]).unwrap();
+        // Rows which are no tiles.
+        connection.execute_batch(
+            "INSERT INTO tiles VALUES (-1, 0, 0, x''), (40, 0, 0, x''), (11, -5, 0, x''),
+                                      (11, 0, 2048, x''), (11, 0, 9999999999, x'')").unwrap();
+    }
+    let mbtiles = MbTiles::open(&path).unwrap();
+    let metadata = mbtiles.metadata().unwrap();
+    assert_eq!(metadata.get("name").map(String::as_str), Some("test"));
+    assert_eq!(mbtiles.zoom_range().unwrap(), Some((10, 12)));
+    let address = TileAddress { z: 12, x: 2138, y: 1388 };
+    assert_eq!(mbtiles.addresses().unwrap(), vec![TileAddress { z: 10, x: 1, y: 1022 }, address]);
+    let tile = mbtiles.get(address).unwrap().unwrap();
+    assert!(::process(tile.as_slice()).unwrap().contains("<path"));
+    assert_eq!(mbtiles.get(TileAddress { z: 12, x: 2138, y: 1387 }).unwrap(), None);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_create_mbtiles() {
+    use std::env;
+    use std::fs;
+
+    let path = env::temp_dir().join(format!("colorful-map-create-{}.mbtiles", ::std::process::id()));
+    let address = TileAddress { z: 3, x: 1, y: 2 };
+    {
+        let mut mbtiles = MbTiles::create(&path).unwrap();
+        mbtiles.set_metadata("name", "created").unwrap();
+        mbtiles.insert_tiles(&[(address, b"tile".to_vec())]).unwrap();
+    }
+    let mbtiles = MbTiles::open(&path).unwrap();
+    assert_eq!(mbtiles.metadata().unwrap().get("name").map(String::as_str), Some("created"));
+    assert_eq!(mbtiles.addresses().unwrap(), vec![address]);
+    assert!(mbtiles.get_raw(address).unwrap().unwrap().starts_with(&[0x1f, 0x8b]));
+    assert_eq!(mbtiles.get(address).unwrap(), Some(b"tile".to_vec()));
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,120 @@
+//! Options to configure how map tiles are processed.
+
+use std::collections::HashMap;
+
+use classes::ClassMapping;
+use filter::Filter;
+
+/// Selects the tags written as `data-*` attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataTags {
+    /// No tags.
+    None,
+    /// All tags of a feature in the order of their keys.
+    All,
+    /// The tags with these keys in this order.
+    Only(Vec<String>),
+}
+
+/// Configures the processing of a tile.
+///
+/// The default options render a tile strictly:
+/// the first invalid feature aborts processing.
+/// Compressed tiles are decompressed.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Skip invalid features instead of failing the whole tile.
+    ///
+    /// Skipped features are reported as warnings together
+    /// with their layer and index. Layers with an extent of zero
+    /// are skipped and reported as well.
+    pub lenient: bool,
+    /// Decompress gzip and zlib compressed tiles.
+    ///
+    /// If disabled the data is always parsed as an uncompressed tile.
+    pub decompress: bool,
+    /// Skip layers with an unknown version instead of failing.
+    ///
+    /// Versions 1 and 2 of the specification are supported.
+    /// Skipped layers are reported as warnings.
+    pub skip_unknown_versions: bool,
+    /// A tag used as id of features without an id.
+    ///
+    /// The specification stores ids in a field of the feature, but
+    /// some schemas use a tag like `id` instead.
+    pub id_tag: Option<String>,
+    /// Report features that share their id with an earlier feature
+    /// of the same layer as warnings.
+    pub report_duplicate_ids: bool,
+    /// Only paint features matching the filter.
+    ///
+    /// Other features are skipped silently, even if their geometry
+    /// is invalid.
+    pub filter: Option<Filter>,
+    /// Only paint the layers with these names.
+    ///
+    /// All layers are painted if this is `None`.
+    pub include_layers: Option<Vec<String>>,
+    /// Do not paint the layers with these names.
+    pub exclude_layers: Vec<String>,
+    /// Paint the layers with these names in this order, each above the
+    /// ones before.
+    ///
+    /// Without an order features of all layers are mixed by their sort
+    /// rank. Layers missing from the list are drawn above the listed
+    /// ones, mixed by sort rank again.
+    pub layer_order: Option<Vec<String>>,
+    /// The sort ranks of features without a `sort_rank` tag by layer name.
+    ///
+    /// Layers missing here use `feature::DEFAULT_SORT_RANK`.
+    pub default_sort_ranks: HashMap<String, u16>,
+    /// Write tags of the features as `data-tag-{key}` attributes.
+    ///
+    /// Keys are mangled with `escape::DataName`, the values are written
+    /// as text: numbers with all digits, booleans as `true` or `false`.
+    pub data_tags: DataTags,
+    /// Decides which tags become classes and attributes in SVG output.
+    ///
+    /// The default mapping writes the classes of `web/style.css`.
+    pub classes: ClassMapping,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            lenient: false,
+            decompress: true,
+            skip_unknown_versions: false,
+            id_tag: None,
+            report_duplicate_ids: false,
+            filter: None,
+            include_layers: None,
+            exclude_layers: Vec::new(),
+            layer_order: None,
+            default_sort_ranks: HashMap::new(),
+            data_tags: DataTags::None,
+            classes: ClassMapping::default(),
+        }
+    }
+}
+
+impl Options {
+    /// Returns true if the layer is painted.
+    pub fn includes_layer(&self, name: &str) -> bool {
+        self.include_layers.as_ref().is_none_or(|names| names.iter().any(|n| n == name))
+            && !self.exclude_layers.iter().any(|n| n == name)
+    }
+
+    /// Returns the rank of the layer in the drawing order.
+    ///
+    /// See `Feature::layer_rank`.
+    pub fn layer_rank(&self, name: &str) -> u16 {
+        match self.layer_order {
+            Some(ref names) => {
+                let rank = names.iter().position(|n| n == name).unwrap_or(names.len());
+                rank.min(u16::MAX as usize) as u16
+            },
+            None => 0,
+        }
+    }
+}
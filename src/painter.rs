@@ -0,0 +1,98 @@
+//! The interface between features and output formats.
+//!
+//! Features do not know how they are drawn. `Feature::paint` decodes
+//! the geometry and calls a painter for each command, the painter
+//! produces the output: SVG elements, canvas commands or pixels.
+
+use feature::Feature;
+use error::Result;
+
+/// Receives the geometry of features command by command.
+///
+/// Coordinates are in pixels of a 256 pixel tile, (0, 0) is in the
+/// upper left corner.
+///
+/// For each feature `begin_feature` is called first, then the commands
+/// of the geometry and at last `end_feature`. Point features consist
+/// of `move_to` calls only, one for each point. If the geometry turns
+/// out to be invalid `abort_feature` is called instead of `end_feature`
+/// and the painter should discard everything painted for this feature.
+pub trait Painter {
+    /// Starts a new feature.
+    ///
+    /// The feature provides the metadata like layer, tags and sort rank.
+    /// An error skips the feature.
+    fn begin_feature(&mut self, feature: &Feature) -> Result<()>;
+
+    /// Starts a new part of the geometry at (x, y).
+    fn move_to(&mut self, x: f32, y: f32);
+
+    /// Draws a line to (x, y).
+    fn line_to(&mut self, x: f32, y: f32);
+
+    /// Closes the current ring of a polygon.
+    fn close_path(&mut self);
+
+    /// Finishes the current feature.
+    fn end_feature(&mut self);
+
+    /// Discards the current feature.
+    fn abort_feature(&mut self);
+}
+
+/// Records the calls as text.
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct Recorder {
+    commands: Vec<String>,
+    feature: Vec<String>,
+}
+
+#[cfg(test)]
+impl Painter for Recorder {
+    fn begin_feature(&mut self, feature: &Feature) -> Result<()> {
+        self.feature.push(format!("begin {} {:?}", feature.layer(), feature.geom_type()));
+        Ok(())
+    }
+
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.feature.push(format!("M {} {}", x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.feature.push(format!("L {} {}", x, y));
+    }
+
+    fn close_path(&mut self) {
+        self.feature.push("Z".to_owned());
+    }
+
+    fn end_feature(&mut self) {
+        self.commands.append(&mut self.feature);
+        self.commands.push("end".to_owned());
+    }
+
+    fn abort_feature(&mut self) {
+        self.feature.clear();
+    }
+}
+
+#[test]
+fn test_painter() {
+    use tag::TagMap;
+    use vector_tile::{Tile_Feature, Tile_GeomType};
+
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_field_type(Tile_GeomType::POLYGON);
+    raw_feature.set_geometry(vec![9, 0, 0, 18, 8, 0, 0, 8, 15]);
+    let feature = Feature::new(&raw_feature, TagMap::new(), "water", 2, 0.5).unwrap();
+    let mut recorder = Recorder::default();
+    feature.paint(&mut recorder).unwrap();
+    assert_eq!(recorder.commands, ["begin water POLYGON", "M 0 0", "L 2 0", "L 2 2", "Z", "end"]);
+
+    // Invalid features leave nothing behind.
+    raw_feature.set_field_type(Tile_GeomType::LINESTRING);
+    let feature = Feature::new(&raw_feature, TagMap::new(), "roads", 2, 0.5).unwrap();
+    assert!(feature.paint(&mut recorder).is_err());
+    assert_eq!(recorder.commands.len(), 6);
+}
@@ -0,0 +1,524 @@
+//! Reads tiles from PMTiles version 3 archives.
+//!
+//! A PMTiles archive is a single file with a fixed size header, a
+//! root directory, optional leaf directories and the tile data.
+//! Tiles are identified by their position on a Hilbert curve over
+//! all zoom levels. Directories are varint encoded and may be
+//! compressed. Like `mbtiles` the reader returns plain MVT data
+//! ready for `process`.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use compression;
+use error::{Error, Result};
+use projection::TileAddress;
+
+/// The length of the header in bytes.
+const HEADER_LEN: usize = 127;
+
+/// Leaf directories may be nested at most this deep.
+const MAX_DEPTH: usize = 4;
+
+/// The number of tiles on zoom levels 0 to 31, tile ids are below.
+const TILE_COUNT: u64 = u64::MAX / 3;
+
+/// Compression of directories, metadata and tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The compression is unknown.
+    Unknown,
+    /// The data is not compressed.
+    None,
+    /// The data is gzip compressed.
+    Gzip,
+    /// The data is brotli compressed.
+    Brotli,
+    /// The data is zstd compressed.
+    Zstd,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Result<Compression> {
+        Ok(match byte {
+            0 => Compression::Unknown,
+            1 => Compression::None,
+            2 => Compression::Gzip,
+            3 => Compression::Brotli,
+            4 => Compression::Zstd,
+            _ => return Err(Error::InvalidPmTiles("unknown compression")),
+        })
+    }
+
+    fn decompress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data),
+            Compression::Gzip if compression::sniff(&data) != Some(compression::Format::Gzip) =>
+                Err(Error::InvalidPmTiles("gzip compressed data expected")),
+            // Guess from the data, tiles are often stored gzip compressed.
+            Compression::Gzip | Compression::Unknown =>
+                Ok(compression::decompress(&data)?.into_owned()),
+            Compression::Brotli => Err(Error::UnsupportedCompression("brotli")),
+            Compression::Zstd => Err(Error::UnsupportedCompression("zstd")),
+        }
+    }
+}
+
+/// The header of a PMTiles archive.
+///
+/// Offsets and lengths are in bytes from the start of the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// The offset of the root directory.
+    pub root_offset: u64,
+    /// The length of the root directory.
+    pub root_length: u64,
+    /// The offset of the JSON metadata.
+    pub metadata_offset: u64,
+    /// The length of the JSON metadata.
+    pub metadata_length: u64,
+    /// The offset of the leaf directories section.
+    pub leaf_offset: u64,
+    /// The length of the leaf directories section.
+    pub leaf_length: u64,
+    /// The offset of the tile data section.
+    pub data_offset: u64,
+    /// The length of the tile data section.
+    pub data_length: u64,
+    /// Compression of directories and metadata.
+    pub internal_compression: Compression,
+    /// Compression of the tiles.
+    pub tile_compression: Compression,
+    /// The format of the tiles, 1 is MVT.
+    pub tile_type: u8,
+    /// The lowest zoom level with tiles.
+    pub min_zoom: u8,
+    /// The highest zoom level with tiles.
+    pub max_zoom: u8,
+}
+
+impl Header {
+    /// Parses the header from the first 127 bytes of an archive.
+    pub fn parse(bytes: &[u8]) -> Result<Header> {
+        if bytes.len() < HEADER_LEN || &bytes[..7] != b"PMTiles" {
+            return Err(Error::InvalidPmTiles("not a PMTiles archive"));
+        }
+        if bytes[7] != 3 {
+            return Err(Error::InvalidPmTiles("only PMTiles version 3 is supported"));
+        }
+        let u64_at = |i: usize| {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(buf)
+        };
+        Ok(Header {
+            root_offset: u64_at(8),
+            root_length: u64_at(16),
+            metadata_offset: u64_at(24),
+            metadata_length: u64_at(32),
+            leaf_offset: u64_at(40),
+            leaf_length: u64_at(48),
+            data_offset: u64_at(56),
+            data_length: u64_at(64),
+            internal_compression: Compression::from_byte(bytes[97])?,
+            tile_compression: Compression::from_byte(bytes[98])?,
+            tile_type: bytes[99],
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+        })
+    }
+}
+
+/// An entry of a directory.
+///
+/// Entries with a run length of zero point to a leaf directory,
+/// all others to tile data used by `run_length` consecutive tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    run_length: u64,
+}
+
+/// An open PMTiles archive.
+#[derive(Debug)]
+pub struct PmTiles<R> {
+    reader: R,
+    header: Header,
+    root: Vec<Entry>,
+}
+
+impl PmTiles<File> {
+    /// Opens an archive file for reading.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PmTiles<File>> {
+        PmTiles::new(File::open(path).map_err(Error::Io)?)
+    }
+}
+
+impl<R: Read + Seek> PmTiles<R> {
+    /// Reads the header and root directory of an archive.
+    pub fn new(mut reader: R) -> Result<PmTiles<R>> {
+        let mut bytes = [0; HEADER_LEN];
+        reader.read_exact(&mut bytes).map_err(Error::Io)?;
+        let header = Header::parse(&bytes)?;
+        let mut archive = PmTiles { reader, header, root: Vec::new() };
+        let (offset, length) = (archive.header.root_offset, archive.header.root_length);
+        archive.root = archive.read_directory(offset, length)?;
+        Ok(archive)
+    }
+
+    /// Returns the header of the archive.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the JSON metadata of the archive.
+    pub fn metadata(&mut self) -> Result<String> {
+        let (offset, length) = (self.header.metadata_offset, self.header.metadata_length);
+        let data = self.read_internal(offset, length)?;
+        String::from_utf8(data).map_err(|_| Error::InvalidPmTiles("metadata is not UTF-8"))
+    }
+
+    /// Returns the addresses of all tiles in the archive.
+    ///
+    /// The tiles are sorted by their tile id.
+    pub fn addresses(&mut self) -> Result<Vec<TileAddress>> {
+        let mut addresses = Vec::new();
+        let root = self.root.clone();
+        self.collect_addresses(&root, 0, &mut addresses)?;
+        Ok(addresses)
+    }
+
+    fn collect_addresses(&mut self, entries: &[Entry], depth: usize,
+                         addresses: &mut Vec<TileAddress>) -> Result<()> {
+        for entry in entries {
+            if entry.run_length == 0 {
+                let leaf = self.read_leaf(entry, depth)?;
+                self.collect_addresses(&leaf, depth + 1, addresses)?;
+                continue;
+            }
+            for tile_id in entry.tile_id..entry.tile_id + entry.run_length {
+                addresses.push(tile_id_to_address(tile_id)
+                    .ok_or(Error::InvalidPmTiles("tile id out of range"))?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the data of a tile as stored in the archive.
+    pub fn get_raw(&mut self, address: TileAddress) -> Result<Option<Vec<u8>>> {
+        let tile_id = match address_to_tile_id(address) {
+            Some(tile_id) => tile_id,
+            None => return Ok(None),
+        };
+        let mut entries = self.root.clone();
+        for depth in 0..MAX_DEPTH {
+            let entry = match find_entry(&entries, tile_id) {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+            if entry.run_length > 0 {
+                let offset = self.header.data_offset.checked_add(entry.offset)
+                    .ok_or(Error::InvalidPmTiles("tile offset out of range"))?;
+                return self.read(offset, entry.length).map(Some);
+            }
+            entries = self.read_leaf(&entry, depth)?;
+        }
+        Err(Error::InvalidPmTiles("leaf directories are nested too deep"))
+    }
+
+    /// Returns the MVT data of a tile.
+    ///
+    /// Compressed tiles are decompressed.
+    pub fn get(&mut self, address: TileAddress) -> Result<Option<Vec<u8>>> {
+        match self.get_raw(address)? {
+            Some(data) => self.header.tile_compression.decompress(data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_leaf(&mut self, entry: &Entry, depth: usize) -> Result<Vec<Entry>> {
+        if depth + 1 >= MAX_DEPTH {
+            return Err(Error::InvalidPmTiles("leaf directories are nested too deep"));
+        }
+        let offset = self.header.leaf_offset.checked_add(entry.offset)
+            .ok_or(Error::InvalidPmTiles("leaf directory offset out of range"))?;
+        self.read_directory(offset, entry.length)
+    }
+
+    fn read_directory(&mut self, offset: u64, length: u64) -> Result<Vec<Entry>> {
+        parse_directory(&self.read_internal(offset, length)?)
+    }
+
+    fn read_internal(&mut self, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let data = self.read(offset, length)?;
+        self.header.internal_compression.decompress(data)
+    }
+
+    fn read(&mut self, offset: u64, length: u64) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.reader.seek(SeekFrom::Start(offset)).map_err(Error::Io)?;
+        (&mut self.reader).take(length).read_to_end(&mut data).map_err(Error::Io)?;
+        if (data.len() as u64) < length {
+            return Err(Error::InvalidPmTiles("unexpected end of archive"));
+        }
+        Ok(data)
+    }
+}
+
+/// Finds the entry containing the tile or the leaf directory that may contain it.
+fn find_entry(entries: &[Entry], tile_id: u64) -> Option<Entry> {
+    // The index of the last entry starting at or before the tile.
+    let i = match entries.binary_search_by_key(&tile_id, |entry| entry.tile_id) {
+        Ok(i) => i,
+        Err(0) => return None,
+        Err(i) => i - 1,
+    };
+    let entry = entries[i];
+    if entry.run_length == 0 || tile_id - entry.tile_id < entry.run_length {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// Decodes a directory.
+///
+/// The entries are stored column by column: tile id deltas, run
+/// lengths, lengths and offsets. An offset of zero means the data
+/// directly follows the previous entry, otherwise it is stored plus one.
+///
+/// Tile ids and runs must stay below zoom level 32.
+fn parse_directory(mut data: &[u8]) -> Result<Vec<Entry>> {
+    let data = &mut data;
+    let count = read_varint(data)? as usize;
+    let mut entries = Vec::with_capacity(count.min(data.len()));
+    let mut tile_id: u64 = 0;
+    for _ in 0..count {
+        tile_id = tile_id.checked_add(read_varint(data)?)
+            .filter(|&tile_id| tile_id < TILE_COUNT)
+            .ok_or(Error::InvalidPmTiles("tile id out of range"))?;
+        entries.push(Entry { tile_id, offset: 0, length: 0, run_length: 0 });
+    }
+    for entry in &mut entries {
+        entry.run_length = read_varint(data)?;
+        if entry.run_length > TILE_COUNT - entry.tile_id {
+            return Err(Error::InvalidPmTiles("run length out of range"));
+        }
+    }
+    for entry in &mut entries {
+        entry.length = read_varint(data)?;
+    }
+    for i in 0..count {
+        let offset = read_varint(data)?;
+        entries[i].offset = if offset == 0 && i > 0 {
+            entries[i - 1].offset.checked_add(entries[i - 1].length)
+                .ok_or(Error::InvalidPmTiles("invalid directory offset"))?
+        } else {
+            offset.checked_sub(1).ok_or(Error::InvalidPmTiles("invalid directory offset"))?
+        };
+    }
+    Ok(entries)
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()
+            .ok_or(Error::InvalidPmTiles("truncated directory"))?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::InvalidPmTiles("varint is too long"))
+}
+
+/// Returns the position of a tile on the Hilbert curves of all zoom levels.
+///
+/// The curves of lower zoom levels come first, so tile 0/0/0 has id 0
+/// and the tiles of zoom level 1 have the ids 1 to 4.
+///
+/// Returns `None` if the tile does not exist.
+pub fn address_to_tile_id(address: TileAddress) -> Option<u64> {
+    if !address.is_valid() {
+        return None;
+    }
+    let (mut x, mut y) = (address.x as u64, address.y as u64);
+    let mut id = zoom_offset(address.z);
+    let n = 1u64 << address.z;
+    let mut s = n >> 1;
+    while s > 0 {
+        let (rx, ry) = ((x & s > 0) as u64, (y & s > 0) as u64);
+        id += s * s * ((3 * rx) ^ ry);
+        rotate(n, &mut x, &mut y, rx, ry);
+        s >>= 1;
+    }
+    Some(id)
+}
+
+/// Returns the tile at a position on the Hilbert curves.
+///
+/// Returns `None` for ids beyond zoom level 31.
+pub fn tile_id_to_address(tile_id: u64) -> Option<TileAddress> {
+    let z = (0..32).take_while(|&z| zoom_offset(z) <= tile_id).last()?;
+    let mut t = tile_id - zoom_offset(z);
+    if t >= 1 << (2 * z as u64) {
+        return None;
+    }
+    let (mut x, mut y) = (0, 0);
+    let mut s = 1;
+    while s < 1u64 << z {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        rotate(s, &mut x, &mut y, rx, ry);
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    TileAddress::new(z, x as u32, y as u32)
+}
+
+/// The number of tiles on all zoom levels below `z`, which must be below 32.
+fn zoom_offset(z: u8) -> u64 {
+    ((1u64 << (2 * z as u64)) - 1) / 3
+}
+
+/// Rotates a quadrant of size `n` so the curve continues in the right direction.
+fn rotate(n: u64, x: &mut u64, y: &mut u64, rx: u64, ry: u64) {
+    if ry == 0 {
+        if rx == 1 {
+            *x = n - 1 - *x;
+            *y = n - 1 - *y;
+        }
+        ::std::mem::swap(x, y);
+    }
+}
+
+/// Builds a gzip compressed archive with the tiles in a leaf directory.
+#[cfg(test)]
+fn build_archive(tiles: &[(TileAddress, &[u8])]) -> Vec<u8> {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), ::flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+    fn directory(entries: &[Entry]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, entries.len() as u64);
+        let mut last = 0;
+        for entry in entries {
+            write_varint(&mut out, entry.tile_id - last);
+            last = entry.tile_id;
+        }
+        for entry in entries {
+            write_varint(&mut out, entry.run_length);
+        }
+        for entry in entries {
+            write_varint(&mut out, entry.length);
+        }
+        for entry in entries {
+            write_varint(&mut out, entry.offset + 1);
+        }
+        gzip(&out)
+    }
+
+    let mut tiles: Vec<_> = tiles.iter().map(|&(a, data)| (address_to_tile_id(a).unwrap(), gzip(data))).collect();
+    tiles.sort_by_key(|&(id, _)| id);
+    let mut data = Vec::new();
+    let mut leaf_entries = Vec::new();
+    for &(tile_id, ref tile) in &tiles {
+        leaf_entries.push(Entry {
+            tile_id, offset: data.len() as u64, length: tile.len() as u64, run_length: 1 });
+        data.extend_from_slice(tile);
+    }
+    let leaf = directory(&leaf_entries);
+    let root = directory(&[Entry {
+        tile_id: tiles[0].0, offset: 0, length: leaf.len() as u64, run_length: 0 }]);
+    let metadata = gzip(b"{\"name\":\"test\"}");
+
+    let root_offset = HEADER_LEN as u64;
+    let metadata_offset = root_offset + root.len() as u64;
+    let leaf_offset = metadata_offset + metadata.len() as u64;
+    let data_offset = leaf_offset + leaf.len() as u64;
+    let mut archive = b"PMTiles\x03".to_vec();
+    for &n in &[root_offset, root.len() as u64, metadata_offset, metadata.len() as u64,
+                leaf_offset, leaf.len() as u64, data_offset, data.len() as u64,
+                tiles.len() as u64, tiles.len() as u64, tiles.len() as u64] {
+        archive.extend_from_slice(&n.to_le_bytes());
+    }
+    // clustered, internal and tile compression, tile type, zoom range
+    archive.extend_from_slice(&[1, 2, 2, 1, 0, 14]);
+    archive.resize(HEADER_LEN, 0);
+    for part in &[root, metadata, leaf, data] {
+        archive.extend_from_slice(part);
+    }
+    archive
+}
+
+#[test]
+fn test_tile_id() {
+    assert_eq!(address_to_tile_id(TileAddress { z: 0, x: 0, y: 0 }), Some(0));
+    let z1: Vec<_> = [(0, 0), (0, 1), (1, 1), (1, 0)].iter()
+        .map(|&(x, y)| address_to_tile_id(TileAddress { z: 1, x, y }).unwrap()).collect();
+    assert_eq!(z1, vec![1, 2, 3, 4]);
+    assert_eq!(address_to_tile_id(TileAddress { z: 2, x: 0, y: 0 }), Some(5));
+    assert_eq!(address_to_tile_id(TileAddress { z: 32, x: 0, y: 0 }), None);
+    assert_eq!(address_to_tile_id(TileAddress { z: 200, x: 0, y: 0 }), None);
+    for &(z, x, y) in &[(3, 5, 2), (12, 2138, 1388), (20, 1000, 999_999), (31, 7, 1 << 30)] {
+        let address = TileAddress::new(z, x, y).unwrap();
+        assert_eq!(tile_id_to_address(address_to_tile_id(address).unwrap()), Some(address));
+    }
+}
+
+#[test]
+fn test_pmtiles() {
+    use std::io::Cursor;
+
+    let tile = compression::decompress(include_bytes!("../web/tile.mvt")).unwrap().into_owned();
+    let address = TileAddress { z: 12, x: 2138, y: 1388 };
+    let other = TileAddress { z: 0, x: 0, y: 0 };
+    let archive = build_archive(&[(address, &tile), (other, b"other")]);
+    let mut pmtiles = PmTiles::new(Cursor::new(archive)).unwrap();
+    assert_eq!(pmtiles.header().tile_compression, Compression::Gzip);
+    assert_eq!((pmtiles.header().min_zoom, pmtiles.header().max_zoom), (0, 14));
+    assert_eq!(pmtiles.metadata().unwrap(), "{\"name\":\"test\"}");
+    assert_eq!(pmtiles.addresses().unwrap(), vec![other, address]);
+    assert_eq!(pmtiles.get(other).unwrap(), Some(b"other".to_vec()));
+    let data = pmtiles.get(address).unwrap().unwrap();
+    assert!(::process(data.as_slice()).unwrap().contains("<path"));
+    assert_eq!(pmtiles.get(TileAddress { z: 12, x: 2138, y: 1387 }).unwrap(), None);
+    assert!(PmTiles::new(Cursor::new(b"PMTiles\x02".to_vec())).is_err());
+}
+
+#[test]
+fn test_invalid_directory() {
+    let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+    let directory = |columns: &[&[&[u8]]]| {
+        let mut data = vec![columns[0].len() as u8];
+        for value in columns.iter().flat_map(|column| column.iter()) {
+            data.extend_from_slice(value);
+        }
+        parse_directory(&data)
+    };
+
+    assert!(directory(&[&[&[5]], &[&[2]], &[&[1]], &[&[1]]]).is_ok());
+    // Tile ids, runs and offsets overflowing.
+    assert!(directory(&[&[&max, &[1]], &[&[1], &[1]], &[&[1], &[1]], &[&[1], &[0]]]).is_err());
+    assert!(directory(&[&[&[5]], &[&max], &[&[1]], &[&[1]]]).is_err());
+    assert!(directory(&[&[&[0], &[1]], &[&[1], &[1]], &[&max, &[1]], &[&max, &[0]]]).is_err());
+}
@@ -0,0 +1,198 @@
+//! Tile addresses and the Web Mercator projection.
+//!
+//! Map tiles are addressed by zoom level `z`, column `x` and row `y`
+//! with the tile (0, 0) in the north-west corner of the map.
+//! Coordinates inside a tile range from 0 to the layer's extent and
+//! can be projected to Web Mercator (EPSG:3857) meters and to
+//! longitude and latitude (WGS84) and back.
+
+use std::f64::consts::PI;
+
+/// The radius of the earth used by Web Mercator in meters.
+pub const EARTH_RADIUS: f64 = 6378137.0;
+
+/// Half the width of the Web Mercator world in meters.
+pub const MERCATOR_MAX: f64 = PI * EARTH_RADIUS;
+
+/// The maximum latitude that can be displayed with Web Mercator.
+pub const MAX_LATITUDE: f64 = 85.0511287798066;
+
+/// The address of a map tile.
+///
+/// Zoom levels go up to 31. Addresses built from the fields directly
+/// may not exist, `is_valid` tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileAddress {
+    /// The zoom level.
+    pub z: u8,
+    /// The column of the tile, counted from the west.
+    pub x: u32,
+    /// The row of the tile, counted from the north.
+    pub y: u32,
+}
+
+impl TileAddress {
+    /// Creates a tile address.
+    ///
+    /// Returns `None` if the tile does not exist on the zoom level.
+    pub fn new(z: u8, x: u32, y: u32) -> Option<TileAddress> {
+        let address = TileAddress { z, x, y };
+        if address.is_valid() { Some(address) } else { None }
+    }
+
+    /// Returns true if the tile exists on its zoom level.
+    pub fn is_valid(&self) -> bool {
+        self.z <= 31 && (self.x as u64) < 1 << self.z && (self.y as u64) < 1 << self.z
+    }
+
+    /// Returns the tile containing the position on the given zoom level.
+    ///
+    /// Returns `None` for zoom levels above 31.
+    pub fn from_lon_lat(lon: f64, lat: f64, z: u8) -> Option<TileAddress> {
+        if z > 31 {
+            return None;
+        }
+        let size = (1u64 << z) as f64;
+        let (fx, fy) = lon_lat_to_fraction(lon, lat);
+        let clamp = |f: f64| (f * size).floor().max(0.0).min(size - 1.0) as u32;
+        Some(TileAddress { z, x: clamp(fx), y: clamp(fy) })
+    }
+
+    /// The number of tiles in a row or column on this zoom level.
+    fn size(&self) -> f64 {
+        2f64.powi(i32::from(self.z))
+    }
+
+    /// Converts tile coordinates to a fraction of the world.
+    ///
+    /// (0, 0) is the north-west and (1, 1) the south-east corner.
+    fn world_fraction(&self, extent: u32, x: f64, y: f64) -> (f64, f64) {
+        let extent = extent as f64;
+        ((self.x as f64 + x / extent) / self.size(),
+         (self.y as f64 + y / extent) / self.size())
+    }
+
+    /// Converts a fraction of the world to tile coordinates.
+    fn tile_position(&self, extent: u32, fx: f64, fy: f64) -> (f64, f64) {
+        let extent = extent as f64;
+        ((fx * self.size() - self.x as f64) * extent,
+         (fy * self.size() - self.y as f64) * extent)
+    }
+
+    /// Projects tile coordinates to Web Mercator meters.
+    pub fn tile_to_mercator(&self, extent: u32, x: f64, y: f64) -> (f64, f64) {
+        let (fx, fy) = self.world_fraction(extent, x, y);
+        ((2.0 * fx - 1.0) * MERCATOR_MAX, (1.0 - 2.0 * fy) * MERCATOR_MAX)
+    }
+
+    /// Projects Web Mercator meters to tile coordinates.
+    ///
+    /// Positions outside of the tile have coordinates outside of 0 to extent.
+    pub fn mercator_to_tile(&self, extent: u32, mx: f64, my: f64) -> (f64, f64) {
+        self.tile_position(extent,
+                           (mx / MERCATOR_MAX + 1.0) / 2.0,
+                           (1.0 - my / MERCATOR_MAX) / 2.0)
+    }
+
+    /// Projects tile coordinates to longitude and latitude.
+    pub fn tile_to_lon_lat(&self, extent: u32, x: f64, y: f64) -> (f64, f64) {
+        let (mx, my) = self.tile_to_mercator(extent, x, y);
+        mercator_to_lon_lat(mx, my)
+    }
+
+    /// Projects longitude and latitude to tile coordinates.
+    pub fn lon_lat_to_tile(&self, extent: u32, lon: f64, lat: f64) -> (f64, f64) {
+        let (fx, fy) = lon_lat_to_fraction(lon, lat);
+        self.tile_position(extent, fx, fy)
+    }
+
+    /// Returns the bounds of the tile as (west, south, east, north) in degrees.
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let (west, north) = self.tile_to_lon_lat(1, 0.0, 0.0);
+        let (east, south) = self.tile_to_lon_lat(1, 1.0, 1.0);
+        (west, south, east, north)
+    }
+
+    /// Returns true if the position is inside of the tile.
+    pub fn contains(&self, lon: f64, lat: f64) -> bool {
+        let (x, y) = self.lon_lat_to_tile(1, lon, lat);
+        (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)
+    }
+
+    /// Returns the tile `dx` columns east and `dy` rows south of this tile.
+    ///
+    /// Columns wrap around the antimeridian, rows do not wrap.
+    pub fn neighbour(&self, dx: i64, dy: i64) -> Option<TileAddress> {
+        if !self.is_valid() {
+            return None;
+        }
+        let size = 1i64 << self.z;
+        let y = self.y as i64 + dy;
+        if y < 0 || y >= size {
+            return None;
+        }
+        let x = (self.x as i64 + dx).rem_euclid(size);
+        Some(TileAddress { z: self.z, x: x as u32, y: y as u32 })
+    }
+
+    /// Returns the tile one zoom level above containing this tile.
+    pub fn parent(&self) -> Option<TileAddress> {
+        if self.z == 0 {
+            return None;
+        }
+        Some(TileAddress { z: self.z - 1, x: self.x / 2, y: self.y / 2 })
+    }
+}
+
+/// Projects longitude and latitude to Web Mercator meters.
+///
+/// Latitudes are clamped to the range Web Mercator can display.
+pub fn lon_lat_to_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    (lon.to_radians() * EARTH_RADIUS,
+     (PI / 4.0 + lat / 2.0).tan().ln() * EARTH_RADIUS)
+}
+
+/// Projects Web Mercator meters to longitude and latitude.
+pub fn mercator_to_lon_lat(mx: f64, my: f64) -> (f64, f64) {
+    ((mx / EARTH_RADIUS).to_degrees(),
+     (my / EARTH_RADIUS).sinh().atan().to_degrees())
+}
+
+fn lon_lat_to_fraction(lon: f64, lat: f64) -> (f64, f64) {
+    let (mx, my) = lon_lat_to_mercator(lon, lat);
+    ((mx / MERCATOR_MAX + 1.0) / 2.0, (1.0 - my / MERCATOR_MAX) / 2.0)
+}
+
+#[test]
+fn test_projection() {
+    let close = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6;
+
+    let world = TileAddress::new(0, 0, 0).unwrap();
+    assert!(close(world.tile_to_mercator(4096, 2048.0, 2048.0), (0.0, 0.0)));
+    assert!(close(world.tile_to_mercator(4096, 0.0, 0.0), (-MERCATOR_MAX, MERCATOR_MAX)));
+    assert!(close(world.tile_to_lon_lat(4096, 4096.0, 4096.0), (180.0, -MAX_LATITUDE)));
+
+    // Frankfurt am Main
+    let tile = TileAddress::from_lon_lat(8.682, 50.111, 12).unwrap();
+    assert_eq!(tile, TileAddress { z: 12, x: 2146, y: 1387 });
+    assert!(tile.contains(8.682, 50.111));
+    let (x, y) = tile.lon_lat_to_tile(4096, 8.682, 50.111);
+    assert!((0.0..4096.0).contains(&x) && (0.0..4096.0).contains(&y));
+    assert!(close(tile.tile_to_lon_lat(4096, x, y), (8.682, 50.111)));
+    let (mx, my) = tile.tile_to_mercator(4096, x, y);
+    assert!(close(tile.mercator_to_tile(4096, mx, my), (x, y)));
+
+    let (west, south, east, north) = tile.bounds();
+    assert!(west < 8.682 && 8.682 < east && south < 50.111 && 50.111 < north);
+    assert_eq!(tile.neighbour(1, 0), TileAddress::new(12, 2147, 1387));
+    assert_eq!(world.neighbour(1, 0), Some(world));
+    assert_eq!(world.neighbour(0, 1), None);
+    let invalid = TileAddress { z: 64, x: 0, y: 0 };
+    assert!(!invalid.is_valid());
+    assert_eq!(invalid.neighbour(1, 0), None);
+    assert!(invalid.bounds().0.is_finite());
+    assert_eq!(TileAddress::from_lon_lat(0.0, 0.0, 32), None);
+    assert_eq!(tile.parent(), TileAddress::new(11, 1073, 693));
+    assert_eq!(TileAddress::new(1, 2, 0), None);
+}
@@ -0,0 +1,362 @@
+//! Renders tiles to PNG images without a browser.
+//!
+//! `RasterPainter` draws the features with anti-aliasing into a square
+//! image of 256 or 512 pixels. The colors and line widths come from a
+//! style function, `web_style` reproduces the stylesheet of the web
+//! map. Labels are not drawn as there are no fonts.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use tiny_skia::{FillRule, Paint, Path, PathBuilder, Pixmap, Stroke, StrokeDash, Transform};
+
+use error::{Error, Result};
+use feature::Feature;
+use options::Options;
+use painter::Painter;
+use tag::Value;
+use vector_tile::Tile_GeomType as GeomType;
+
+/// An RGB color.
+pub type Color = (u8, u8, u8);
+
+/// How a feature is drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    /// The color of the area, `None` for no fill.
+    pub fill: Option<Color>,
+    /// The color of the outline, `None` for no outline.
+    pub stroke: Option<Color>,
+    /// The width of the outline in pixels of a 256 pixel tile.
+    pub stroke_width: f32,
+    /// The radius of the markers of points in pixels of a 256 pixel tile.
+    pub radius: f32,
+    /// Alternating lengths of dashes and gaps, empty for solid lines.
+    pub dashes: Vec<f32>,
+    /// The opacity of the area from 0 (invisible) to 1.
+    pub fill_opacity: f32,
+    /// The opacity of the outline from 0 (invisible) to 1.
+    pub stroke_opacity: f32,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            fill: None,
+            stroke: None,
+            stroke_width: 1.0,
+            radius: 1.0,
+            dashes: Vec::new(),
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+        }
+    }
+}
+
+const WATER: Color = (0x5a, 0xc5, 0xe3);
+const BORDER: Color = (139, 0, 0);
+const PROTECTED_LANDS: Color = (0, 128, 0);
+const DARK_GREY: Color = (169, 169, 169);
+
+/// The style of `web/style.css` for a feature.
+///
+/// Returns `None` for invisible features.
+pub fn web_style(feature: &Feature) -> Option<Style> {
+    let kind = feature.tags().get("kind").and_then(Value::str)?;
+    let fill = |color| Style { fill: Some(color), ..Style::default() };
+    let stroke = |color, stroke_width| Style {
+        stroke: Some(color),
+        stroke_width,
+        ..Style::default()
+    };
+    let mut style = match kind {
+        // Boundaries.
+        "country" => stroke(BORDER, 1.0),
+        "county" => Style { dashes: vec![1.0, 1.0], ..stroke(BORDER, 1.0) },
+        "macroregion" => stroke(BORDER, 0.7),
+        "region" => stroke(BORDER, 0.5),
+        // Buildings and landuse.
+        "building" | "building_part" => fill((221, 160, 221)),
+        "cemetery" | "graveyard" | "urban_area" => fill(DARK_GREY),
+        "commercial" => fill((0xbb, 0xd5, 0xd2)),
+        "farmland" => fill((238, 232, 170)),
+        "forest" => fill((0xad, 0xd1, 0x9e)),
+        "grass" => fill((144, 238, 144)),
+        "industrial" => fill((0xd5, 0xcf, 0xbb)),
+        "meadow" => fill((152, 251, 152)),
+        "national_park" | "protected_area" =>
+            Style { fill_opacity: 0.5, ..fill(PROTECTED_LANDS) },
+        "park" => fill((0, 255, 127)),
+        "parking" => fill((255, 255, 255)),
+        "residential" => fill((192, 192, 192)),
+        "scrub" => fill((0x83, 0xa6, 0x47)),
+        // Roads.
+        "aeroway" => stroke(DARK_GREY, 3.0),
+        "highway" => stroke((220, 20, 60), 2.0),
+        "major_road" => stroke((255, 165, 0), 1.0),
+        "minor_road" => stroke((255, 255, 255), 1.0),
+        "path" => stroke((165, 42, 42), 0.4),
+        // Note: the stylesheet's width of `0.5.px` is invalid, browsers use the default.
+        "rail" => stroke((0, 0, 0), 1.0),
+        // Water.
+        "basin" | "dock" | "lake" | "ocean" | "riverbank" | "swimming_pool" | "water" =>
+            fill(WATER),
+        "canal" | "ditch" | "drain" | "river" | "stream" => stroke(WATER, 1.0),
+        _ => return None,
+    };
+    if feature.tags().get("boundary").is_some_and(Value::yes) {
+        style.fill = None;
+    }
+    if style.fill.is_none() && style.stroke.is_none() {
+        return None;
+    }
+    Some(style)
+}
+
+/// Chooses the style of a feature.
+pub type StyleFn = dyn Fn(&Feature) -> Option<Style>;
+
+/// Chooses any number of styles for a feature, each with the draw order
+/// it is drawn at.
+pub type StylesFn = dyn Fn(&Feature) -> Vec<(u32, Style)>;
+
+/// A path waiting to be drawn.
+struct Shape {
+    path: Path,
+    style: Style,
+    fill_rule: FillRule,
+}
+
+/// The feature that is currently painted.
+struct Current {
+    builder: PathBuilder,
+    // The positions of a point feature, the radius depends on the style.
+    points: Vec<(f32, f32)>,
+    styles: Vec<(u32, Style)>,
+    fill_rule: FillRule,
+    point: bool,
+}
+
+/// Draws features into an image.
+///
+/// Features are collected first and drawn in the order of their sort
+/// rank like in the SVG output, see `Feature::draw_order`.
+pub struct RasterPainter {
+    size: u32,
+    styles: Box<StylesFn>,
+    shapes: BTreeMap<u32, Vec<Shape>>,
+    current: Option<Current>,
+}
+
+impl RasterPainter {
+    /// Creates a painter for an image of `size` by `size` pixels using `web_style`.
+    pub fn new(size: u32) -> Result<RasterPainter> {
+        RasterPainter::with_style(size, web_style)
+    }
+
+    /// Creates a painter using the given style function.
+    ///
+    /// Features without a style are not drawn.
+    pub fn with_style<F>(size: u32, style: F) -> Result<RasterPainter>
+            where F: Fn(&Feature) -> Option<Style> + 'static {
+        RasterPainter::with_styles(size, move |feature| {
+            style(feature).map(|style| (feature.draw_order(), style)).into_iter().collect()
+        })
+    }
+
+    /// Creates a painter that may draw a feature several times.
+    ///
+    /// The shapes are drawn in the order given by the style function
+    /// instead of the sort rank, like the style layers of a GL style.
+    pub fn with_styles<F>(size: u32, styles: F) -> Result<RasterPainter>
+            where F: Fn(&Feature) -> Vec<(u32, Style)> + 'static {
+        if size == 0 || size > 4096 {
+            return Err(Error::InvalidImageSize(size));
+        }
+        Ok(RasterPainter {
+            size,
+            styles: Box::new(styles),
+            shapes: BTreeMap::new(),
+            current: None,
+        })
+    }
+
+    /// Draws all features and returns the image.
+    pub fn into_pixmap(self) -> Pixmap {
+        let mut pixmap = Pixmap::new(self.size, self.size).expect("the size was checked");
+        let scale = self.size as f32 / 256.0;
+        let transform = Transform::from_scale(scale, scale);
+        for shape in self.shapes.values().flatten() {
+            let style = &shape.style;
+            let mut paint = Paint { anti_alias: true, ..Paint::default() };
+            let alpha = |opacity: f32| (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+            if let Some((r, g, b)) = style.fill {
+                paint.set_color_rgba8(r, g, b, alpha(style.fill_opacity));
+                pixmap.fill_path(&shape.path, &paint, shape.fill_rule, transform, None);
+            }
+            if let Some((r, g, b)) = style.stroke {
+                paint.set_color_rgba8(r, g, b, alpha(style.stroke_opacity));
+                let stroke = Stroke {
+                    width: style.stroke_width,
+                    dash: stroke_dash(&style.dashes),
+                    ..Stroke::default()
+                };
+                pixmap.stroke_path(&shape.path, &paint, &stroke, transform, None);
+            }
+        }
+        pixmap
+    }
+
+    /// Draws all features and encodes the image as PNG.
+    pub fn into_png(self) -> Vec<u8> {
+        self.into_pixmap().encode_png().expect("encoding a pixmap in memory never fails")
+    }
+}
+
+impl Painter for RasterPainter {
+    fn begin_feature(&mut self, feature: &Feature) -> Result<()> {
+        let styles = (self.styles)(feature);
+        self.current = if styles.is_empty() {
+            None
+        } else {
+            Some(Current {
+                builder: PathBuilder::new(),
+                points: Vec::new(),
+                styles,
+                // Like the SVG output: even-odd for version 1, non-zero for version 2.
+                fill_rule: if feature.version() < 2 { FillRule::EvenOdd } else { FillRule::Winding },
+                point: feature.geom_type() == GeomType::POINT,
+            })
+        };
+        Ok(())
+    }
+
+    fn move_to(&mut self, x: f32, y: f32) {
+        if let Some(ref mut current) = self.current {
+            if current.point {
+                current.points.push((x, y));
+            } else {
+                current.builder.move_to(x, y);
+            }
+        }
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        if let Some(ref mut current) = self.current {
+            current.builder.line_to(x, y);
+        }
+    }
+
+    fn close_path(&mut self) {
+        if let Some(ref mut current) = self.current {
+            current.builder.close();
+        }
+    }
+
+    fn end_feature(&mut self) {
+        let current = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        let path = current.builder.finish();
+        for (draw_order, style) in current.styles {
+            let path = if current.point {
+                // Points are drawn as markers.
+                let mut builder = PathBuilder::new();
+                for &(x, y) in &current.points {
+                    builder.push_circle(x, y, style.radius);
+                }
+                builder.finish()
+            } else {
+                path.clone()
+            };
+            // Empty geometries have no path.
+            if let Some(path) = path {
+                self.shapes.entry(draw_order).or_default().push(Shape {
+                    path,
+                    style,
+                    fill_rule: current.fill_rule,
+                });
+            }
+        }
+    }
+
+    fn abort_feature(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Builds the dash pattern of a stroke.
+///
+/// Negative and non-finite lengths are left out, odd-length lists are
+/// repeated like in SVG. `None` draws a solid line.
+fn stroke_dash(dashes: &[f32]) -> Option<StrokeDash> {
+    let mut dashes: Vec<f32> = dashes.iter().cloned()
+        .filter(|dash| dash.is_finite() && *dash >= 0.0).collect();
+    if dashes.len() % 2 == 1 {
+        dashes.extend_from_within(..);
+    }
+    StrokeDash::new(dashes, 0.0)
+}
+
+/// Reads a Vector File and renders a PNG image of `size` by `size` pixels.
+///
+/// Use `paint` with a `RasterPainter` to get the warnings.
+pub fn render_png<R: Read>(r: R, options: &Options, size: u32) -> Result<Vec<u8>> {
+    let mut painter = RasterPainter::new(size)?;
+    ::paint(r, options, &mut painter)?;
+    Ok(painter.into_png())
+}
+
+#[test]
+fn test_render_png() {
+    use encoder::{LayerBuilder, TileBuilder};
+    use geometry::{Geometry, Polygon};
+    use tag::Value;
+
+    // A rectangle covering the left half of the tile.
+    let rectangle = Polygon::new(vec![(0, 0), (128, 0), (128, 256), (0, 256)], Vec::new()).unwrap();
+    let rectangle = Geometry::Polygon(rectangle);
+    let mut water = LayerBuilder::new("water", 256);
+    water.add_feature(None, &rectangle, &[("kind", Value::String("lake"))]).unwrap();
+    let mut tile = TileBuilder::new();
+    tile.add_layer(water);
+    let bytes = tile.to_bytes().unwrap();
+
+    let mut painter = RasterPainter::new(512).unwrap();
+    ::paint(bytes.as_slice(), &Options::default(), &mut painter).unwrap();
+    let pixmap = painter.into_pixmap();
+    assert_eq!(pixmap.width(), 512);
+    let water = pixmap.pixel(100, 300).unwrap();
+    assert_eq!((water.red(), water.green(), water.blue(), water.alpha()), (0x5a, 0xc5, 0xe3, 255));
+    assert_eq!(pixmap.pixel(400, 300).unwrap().alpha(), 0);
+
+    let png = render_png(bytes.as_slice(), &Options::default(), 256).unwrap();
+    assert!(png.starts_with(b"\x89PNG\r\n"));
+    assert!(RasterPainter::new(0).is_err());
+}
+
+#[test]
+fn test_dashes() {
+    use tag::TagMap;
+    use vector_tile::Tile_Feature;
+
+    // A horizontal line through the middle of the tile.
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_field_type(GeomType::LINESTRING);
+    raw_feature.set_geometry(vec![9, 0, 256, 10, 512, 0]);
+    let feature = Feature::new(&raw_feature, TagMap::new(), "roads", 2, 1.0).unwrap();
+    let paint = |dashes: Vec<f32>| {
+        let style = Style { stroke: Some((0, 0, 0)), stroke_width: 2.0, dashes, ..Style::default() };
+        let mut painter = RasterPainter::with_style(256, move |_| Some(style.clone())).unwrap();
+        feature.paint(&mut painter).unwrap();
+        let pixmap = painter.into_pixmap();
+        (pixmap.pixel(2, 128).unwrap().alpha(), pixmap.pixel(6, 128).unwrap().alpha())
+    };
+
+    assert_eq!(paint(vec![4.0, 4.0]), (255, 0));
+    // An odd-length list is repeated to [4, 4].
+    assert_eq!(paint(vec![4.0]), (255, 0));
+    assert_eq!(paint(vec![4.0, -1.0, f32::NAN]), (255, 0));
+    assert_eq!(paint(Vec::new()), (255, 255));
+}
@@ -1,7 +1,6 @@
 //! Provides a specialized storage for tiles.
 
 use std::collections::BTreeMap;
-use std::fmt::{self, Write};
 
 /// Stores the visualization of a map tile.
 ///
@@ -14,11 +13,11 @@ use std::fmt::{self, Write};
 /// This storage stores a string for each rank used. One can push
 /// new data to each rank. In the end the data is serialized in the
 /// correct order.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Storage {
     // Note: BTreeMap is used because the storage needs to be
     // iterated in correct order when "painting".
-    data: BTreeMap<u16, String>,
+    data: BTreeMap<u32, String>,
     // Note: Keep track of the total data size to avoid reallocations
     // when painting.
     size: usize,
@@ -36,13 +35,10 @@ impl Storage {
     /// Selects a 'sort_rank' and returns it for editing.
     ///
     /// If there is no string for the given rank it is created.
-    pub fn select(&mut self, sort_rank: u16) -> Rank {
-        if !self.data.contains_key(&sort_rank) {
-            self.data.insert(sort_rank, String::new());
-        }
+    /// See `Feature::draw_order` for ranks across layers.
+    pub fn select(&mut self, sort_rank: u32) -> Rank<'_> {
         Rank {
-            selected: self.data.get_mut(&sort_rank)
-                               .expect("The map is not empty for the given rank."),
+            selected: self.data.entry(sort_rank).or_default(),
             size: &mut self.size
         }
     }
@@ -80,14 +76,6 @@ impl<'a> Rank<'a> {
         (*self.size) += s.len();
         (*self.selected).push_str(s);
     }
-
-    /// Add a formatted string to the end of the text.
-    pub fn push_format(&mut self, args: fmt::Arguments) {
-        let len = self.selected.len();
-        (*self.selected).write_fmt(args)
-            .expect("writing a string never fails");
-        (*self.size) += self.selected.len() - len;
-    }
 }
 
 #[test]
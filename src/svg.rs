@@ -0,0 +1,356 @@
+//! Paints features as SVG elements.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use classes::ClassMapping;
+use error::Result;
+use escape::{ClassToken, DataName, Escaped};
+use feature::Feature;
+use glstyle::{GlStyle, LayerType};
+use options::DataTags;
+use painter::Painter;
+use storage::Storage;
+use tag::Value;
+use vector_tile::Tile_GeomType as GeomType;
+
+/// How a GL style is written into the SVG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StyleOutput {
+    /// Each element gets a `style` attribute.
+    Inline,
+    /// Each element gets the `gl-{id}` class of its style layer,
+    /// `GlStyle::stylesheet` provides the rules.
+    Classes,
+}
+
+/// Writes each feature as an SVG element into a `Storage`.
+///
+/// Lines and polygons become paths, points become circles followed by
+/// a text element if the feature has a name. The elements carry the
+/// layer, kind and other tags as classes for the stylesheet, see
+/// `ClassMapping`. With a GL style they only carry the `gl-{id}`
+/// class, the id and the data tags instead, as the style selects
+/// features by their tags and not by the classes of a schema.
+///
+/// With a GL style a feature gets an element for every style layer
+/// that selects it and the elements are ordered by the style layers
+/// instead of the sort rank.
+#[derive(Debug)]
+pub struct SvgPainter {
+    storage: Storage,
+    gl: Option<(Arc<GlStyle>, f64, StyleOutput)>,
+    data_tags: DataTags,
+    class_mapping: ClassMapping,
+    // The elements of the current feature, empty if it is not drawn.
+    elements: Vec<Element>,
+    geom_type: GeomType,
+    // Attributes of the current feature.
+    metadata: String,
+    // The escaped name of the current point feature.
+    label: Option<String>,
+    // The path data of the current line or polygon.
+    path: String,
+    // The positions of the current point feature.
+    points: Vec<(f32, f32)>,
+    // Set for polygons of version 1 tiles.
+    even_odd: bool,
+}
+
+/// An element written for the current feature.
+#[derive(Debug)]
+struct Element {
+    draw_order: u32,
+    // The `gl-{id}` class and the inline style of a GL style layer.
+    class: String,
+    style: String,
+    // Whether the element draws the shape, the label of a point or both.
+    shape: bool,
+    label: bool,
+}
+
+impl SvgPainter {
+    /// Creates a painter with an empty storage.
+    pub fn new() -> SvgPainter {
+        SvgPainter {
+            storage: Storage::new(),
+            gl: None,
+            data_tags: DataTags::None,
+            class_mapping: ClassMapping::default(),
+            elements: Vec::new(),
+            geom_type: GeomType::UNKNOWN,
+            metadata: String::new(),
+            label: None,
+            path: String::new(),
+            points: Vec::new(),
+            even_odd: false,
+        }
+    }
+
+    /// Creates a painter that styles features with a GL style at a zoom level.
+    ///
+    /// Features not selected by any style layer are left out.
+    pub fn with_gl_style(style: Arc<GlStyle>, zoom: f64, output: StyleOutput) -> SvgPainter {
+        SvgPainter { gl: Some((style, zoom, output)), ..SvgPainter::new() }
+    }
+
+    /// Writes the selected tags as `data-tag-{key}` attributes.
+    pub fn with_data_tags(self, data_tags: DataTags) -> SvgPainter {
+        SvgPainter { data_tags, ..self }
+    }
+
+    /// Decides which tags become classes and attributes.
+    ///
+    /// Not used with a GL style.
+    pub fn with_class_mapping(self, class_mapping: ClassMapping) -> SvgPainter {
+        SvgPainter { class_mapping, ..self }
+    }
+
+    fn write_metadata(&mut self, feature: &Feature) -> Result<()> {
+        let tags = feature.tags();
+        let out = &mut self.metadata;
+        // (class="{classes}")? (data-id="{}")? ({attribute}="{}")* (data-tag-{}="{}")*
+        let (classes, attributes) = match self.gl {
+            // The `gl-{id}` class is written per element.
+            Some(_) => (Vec::new(), Vec::new()),
+            None => (self.class_mapping.classes(feature)?, self.class_mapping.attributes(feature)?),
+        };
+        if !classes.is_empty() {
+            write!(out, " class=\"{}\"", classes.join(" ")).unwrap();
+        }
+        if let Some(id) = feature.id() {
+            write!(out, " data-id=\"{}\"", id).unwrap();
+        }
+        for (name, value) in attributes {
+            write!(out, " {}=\"{}\"", name, Escaped(&value)).unwrap();
+        }
+        let keys: Vec<&str> = match self.data_tags {
+            DataTags::None => Vec::new(),
+            DataTags::All => {
+                let mut keys: Vec<&str> = tags.keys().cloned().collect();
+                keys.sort_unstable();
+                keys
+            },
+            DataTags::Only(ref keys) => keys.iter().enumerate()
+                // Attributes must not be repeated.
+                .filter(|&(i, key)| !keys[..i].contains(key))
+                .map(|(_, key)| key.as_str()).collect(),
+        };
+        for key in keys {
+            if let Some(value) = tags.get(key) {
+                write!(out, " data-tag-{}=\"{}\"", DataName(key), Escaped(&value.to_string())).unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an element of the current feature.
+    fn write_element(&self, element: &Element, out: &mut String) {
+        let attributes = format!("{}{}{}", element.class, self.metadata, element.style);
+        match self.geom_type {
+            // Note: in version 2 exterior rings and holes have opposite winding
+            // orders and SVG's default nonzero rule cuts the holes. Version 1
+            // does not define the winding order, so the even-odd rule is used.
+            GeomType::LINESTRING | GeomType::POLYGON if element.shape => {
+                let fill_rule = if self.even_odd { " fill-rule=\"evenodd\"" } else { "" };
+                writeln!(out, "<path{}{} d=\"{}\"></path>", attributes, fill_rule, self.path).unwrap();
+            },
+            // Points are drawn as markers and may carry a label.
+            GeomType::POINT => for &(x, y) in &self.points {
+                if element.shape {
+                    // Note: the radius of a GL style is part of the style.
+                    let radius = if self.gl.is_some() { "" } else { " r=\"1\"" };
+                    writeln!(out, "<circle{} cx=\"{}\" cy=\"{}\"{}></circle>",
+                        attributes, x, y, radius).unwrap();
+                }
+                if let (true, Some(label)) = (element.label, &self.label) {
+                    writeln!(out, "<text{} x=\"{}\" y=\"{}\">{}</text>", attributes, x, y, label).unwrap();
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Default for SvgPainter {
+    fn default() -> SvgPainter {
+        SvgPainter::new()
+    }
+}
+
+impl Painter for SvgPainter {
+    fn begin_feature(&mut self, feature: &Feature) -> Result<()> {
+        self.abort_feature();
+        if let Some((style, zoom, output)) = self.gl.clone() {
+            for layer in style.feature_styles(feature, zoom) {
+                let (class, style) = match output {
+                    StyleOutput::Classes =>
+                        (format!(" class=\"gl-{}\"", ClassToken(layer.id)), String::new()),
+                    StyleOutput::Inline => (String::new(), format!(" style=\"{}\"",
+                        Escaped(&layer.declarations.iter()
+                            .map(|&(property, ref value)| format!("{}: {};", property, value))
+                            .collect::<Vec<_>>().join(" ")))),
+                };
+                let label = layer.kind == LayerType::Symbol;
+                self.elements.push(Element {
+                    draw_order: layer.index as u32,
+                    class,
+                    style,
+                    shape: !label,
+                    label,
+                });
+            }
+            if self.elements.is_empty() {
+                return Ok(());
+            }
+        } else {
+            self.elements.push(Element {
+                draw_order: feature.draw_order(),
+                class: String::new(),
+                style: String::new(),
+                shape: true,
+                label: true,
+            });
+        }
+        self.write_metadata(feature)?;
+        self.geom_type = feature.geom_type();
+        self.even_odd = self.geom_type == GeomType::POLYGON && feature.version() < 2;
+        self.label = feature.tags().get("name").and_then(Value::str)
+            .map(|name| Escaped(name).to_string());
+        Ok(())
+    }
+
+    fn move_to(&mut self, x: f32, y: f32) {
+        if self.elements.is_empty() {
+            return;
+        }
+        if self.geom_type == GeomType::POINT {
+            self.points.push((x, y));
+        } else {
+            write!(self.path, "M {} {} ", x, y).unwrap();
+        }
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        if !self.elements.is_empty() {
+            write!(self.path, "L {} {} ", x, y).unwrap();
+        }
+    }
+
+    fn close_path(&mut self) {
+        if !self.elements.is_empty() {
+            self.path.push_str("Z ");
+        }
+    }
+
+    fn end_feature(&mut self) {
+        let mut out = String::new();
+        for element in &self.elements {
+            out.clear();
+            self.write_element(element, &mut out);
+            self.storage.select(element.draw_order).push_str(&out);
+        }
+        self.abort_feature();
+    }
+
+    fn abort_feature(&mut self) {
+        self.elements.clear();
+        self.metadata.clear();
+        self.path.clear();
+        self.points.clear();
+    }
+}
+
+// Serialize the painted features in the order of their ranks.
+impl From<SvgPainter> for String {
+    fn from(painter: SvgPainter) -> String {
+        String::from(painter.storage)
+    }
+}
+
+#[test]
+fn test_data_tags() {
+    use tag::TagMap;
+    use vector_tile::Tile_Feature;
+
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_field_type(GeomType::LINESTRING);
+    raw_feature.set_geometry(vec![9, 0, 0, 10, 4, 4]);
+    let mut tags = TagMap::new();
+    tags.insert("kind", Value::String("path"));
+    tags.insert("name:en", Value::String("\"A\" & B"));
+    tags.insert("width", Value::Float32(0.1));
+    tags.insert("layer", Value::Int64(-1));
+    tags.insert("oneway", Value::Bool(true));
+    let feature = Feature::new(&raw_feature, tags, "roads", 2, 1.0).unwrap();
+    let paint = |data_tags| {
+        let mut painter = SvgPainter::new().with_data_tags(data_tags);
+        feature.paint(&mut painter).unwrap();
+        String::from(painter)
+    };
+
+    assert_eq!(paint(DataTags::All),
+        "<path class=\"layer-roads kind-path min-zoom-0\" data-tag-kind=\"path\" data-tag-layer=\"-1\" \
+         data-tag-name.3aen=\"&quot;A&quot; &amp; B\" data-tag-oneway=\"true\" data-tag-width=\"0.1\" \
+         d=\"M 0 0 L 2 2 \"></path>\n");
+    let only = DataTags::Only(vec!["width".to_owned(), "ref".to_owned(), "width".to_owned()]);
+    assert!(paint(only).contains("min-zoom-0\" data-tag-width=\"0.1\" d="));
+}
+
+#[test]
+fn test_gl_style_without_kind() {
+    use tag::TagMap;
+    use vector_tile::Tile_Feature;
+
+    let mut raw_feature = Tile_Feature::new();
+    raw_feature.set_id(3);
+    raw_feature.set_field_type(GeomType::LINESTRING);
+    raw_feature.set_geometry(vec![9, 0, 0, 10, 4, 4]);
+    let mut tags = TagMap::new();
+    tags.insert("class", Value::String("motorway"));
+    let feature = Feature::new(&raw_feature, tags, "transportation", 2, 1.0).unwrap();
+    let style = Arc::new(GlStyle::from_json(r##"{"layers": [
+        {"id": "motorway", "type": "line", "source-layer": "transportation",
+         "filter": ["==", "class", "motorway"], "paint": {"line-color": "#f00"}}
+    ]}"##).unwrap().0);
+    let paint = |output| {
+        let mut painter = SvgPainter::with_gl_style(style.clone(), 12.0, output);
+        feature.paint(&mut painter).unwrap();
+        String::from(painter)
+    };
+
+    assert_eq!(paint(StyleOutput::Inline),
+        "<path data-id=\"3\" style=\"fill: none; stroke: #f00; stroke-width: 0.5;\" \
+         d=\"M 0 0 L 2 2 \"></path>\n");
+    assert_eq!(paint(StyleOutput::Classes),
+        "<path class=\"gl-motorway\" data-id=\"3\" d=\"M 0 0 L 2 2 \"></path>\n");
+}
+
+#[test]
+fn test_gl_style_order() {
+    use tag::TagMap;
+    use vector_tile::Tile_Feature;
+
+    let mut raw_road = Tile_Feature::new();
+    raw_road.set_field_type(GeomType::LINESTRING);
+    raw_road.set_geometry(vec![9, 0, 0, 10, 4, 4]);
+    let road = Feature::new(&raw_road, TagMap::new(), "roads", 2, 1.0).unwrap();
+    let mut raw_water = Tile_Feature::new();
+    raw_water.set_field_type(GeomType::POLYGON);
+    raw_water.set_geometry(vec![9, 0, 0, 18, 4, 0, 0, 4, 15]);
+    let water = Feature::new(&raw_water, TagMap::new(), "water", 2, 1.0).unwrap();
+    let style = Arc::new(GlStyle::from_json(r##"{"layers": [
+        {"id": "casing", "type": "line", "source-layer": "roads"},
+        {"id": "water", "type": "fill", "source-layer": "water"},
+        {"id": "road", "type": "line", "source-layer": "roads"}
+    ]}"##).unwrap().0);
+    let mut painter = SvgPainter::with_gl_style(style, 12.0, StyleOutput::Classes);
+    road.paint(&mut painter).unwrap();
+    water.paint(&mut painter).unwrap();
+
+    // The road is drawn once per style layer, below and above the water.
+    assert_eq!(String::from(painter),
+        "<path class=\"gl-casing\" d=\"M 0 0 L 2 2 \"></path>\n\
+         <path class=\"gl-water\" d=\"M 0 0 L 2 0 L 2 2 Z \"></path>\n\
+         <path class=\"gl-road\" d=\"M 0 0 L 2 2 \"></path>\n");
+}
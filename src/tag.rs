@@ -1,8 +1,9 @@
 //! Describes tag values and provides the TagMap type.
 
 use std::collections::HashMap;
+use std::fmt;
 
-use protobuf::{ProtobufError, ProtobufResult};
+use error::{Error, Result};
 
 use vector_tile::Tile_Value;
 
@@ -27,7 +28,7 @@ impl<'a> Value<'a> {
     /// Returns a Value for a Tile_Value.
     ///
     /// Panics if the value is empty.
-    pub fn from_tile_value(value: &Tile_Value) -> ProtobufResult<Value> {
+    pub fn from_tile_value(value: &Tile_Value) -> Result<Value<'_>> {
         use self::Value::*;
         Ok(if value.has_string_value() {
             String(value.get_string_value())
@@ -44,8 +45,7 @@ impl<'a> Value<'a> {
         } else if value.has_bool_value() {
             Bool(value.get_bool_value())
         } else {
-            return Err(ProtobufError::WireError(
-                "mvt: A value must contain data.".to_owned()));
+            return Err(Error::EmptyValue);
         })
     }
 
@@ -59,6 +59,16 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Converts a non-negative integer to u64.
+    pub fn u64(&self) -> Option<u64> {
+        use self::Value::*;
+        match *self {
+            Int64(x) if x >= 0 => Some(x as u64),
+            Uint64(x) => Some(x),
+            _ => None,
+        }
+    }
+
     /// Converts the value to u16, if possible.
     pub fn u16(&self) -> Option<u16> {
         use self::Value::*;
@@ -90,6 +100,18 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Returns the value of any number type as f64.
+    pub fn number(&self) -> Option<f64> {
+        use self::Value::*;
+        match *self {
+            Float32(x) => Some(x as f64),
+            Float64(x) => Some(x),
+            Int64(x) => Some(x as f64),
+            Uint64(x) => Some(x as f64),
+            _ => None,
+        }
+    }
+
     /// Returns the value as f32, if possible.
     ///
     /// Note f64 is converted but no integer types.
@@ -102,3 +124,18 @@ impl<'a> Value<'a> {
         }
     }
 }
+
+// Numbers are written with as many digits as needed to read them back.
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Value::*;
+        match *self {
+            String(x) => f.write_str(x),
+            Float32(x) => write!(f, "{}", x),
+            Float64(x) => write!(f, "{}", x),
+            Int64(x) => write!(f, "{}", x),
+            Uint64(x) => write!(f, "{}", x),
+            Bool(x) => write!(f, "{}", x),
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//! Cuts GeoJSON into a pyramid of vector tiles.
+//!
+//! Features are projected from WGS84 to Web Mercator once and then
+//! clipped to each tile they touch (plus a buffer so lines and polygons
+//! continue smoothly across tile edges), simplified and quantized to
+//! the extent of the tile. The pyramid is built from the top: each tile
+//! is clipped from the features of its parent tile, so deep zoom levels
+//! only look at the features nearby.
+//!
+//! All encoded tiles are kept in memory until cutting is done, which
+//! limits the area and zoom levels that can be cut at once.
+//!
+//! Positions are stored as fractions of the world with (0, 0) in the
+//! north-west and (1, 1) in the south-east corner until they are
+//! quantized.
+
+use serde_json::{self, Map, Value as Json};
+
+use encoder::{LayerBuilder, TileBuilder};
+use error::{Error, Result};
+use geometry::{Geometry as TileGeometry, MultiPolygon, Polygon};
+use projection::{lon_lat_to_mercator, TileAddress, MERCATOR_MAX};
+use tag::Value;
+
+/// Configures how GeoJSON is cut into tiles.
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+    /// The name of the layer containing all features.
+    pub layer: String,
+    /// The lowest zoom level to create tiles for.
+    pub min_zoom: u8,
+    /// The highest zoom level to create tiles for.
+    pub max_zoom: u8,
+    /// The size of a tile in tile coordinates.
+    pub extent: u32,
+    /// The distance features extend beyond the tile edges in tile coordinates.
+    pub buffer: u32,
+    /// The maximum distance a simplified line may deviate from the
+    /// original line in tile coordinates. Zero disables simplification.
+    pub tolerance: f64,
+}
+
+impl Default for TileOptions {
+    fn default() -> TileOptions {
+        TileOptions {
+            layer: "geojson".to_owned(),
+            min_zoom: 0,
+            max_zoom: 14,
+            extent: 4096,
+            buffer: 64,
+            tolerance: 4.0,
+        }
+    }
+}
+
+/// A position as a fraction of the world.
+type Position = (f64, f64);
+
+/// The geometry of a feature with positions as fractions of the world.
+#[derive(Debug, Clone, PartialEq)]
+enum Geometry {
+    Points(Vec<Position>),
+    Lines(Vec<Vec<Position>>),
+    Polygons(Vec<Vec<Vec<Position>>>),
+}
+
+/// A tag value owning its data.
+#[derive(Debug, Clone, PartialEq)]
+enum Tag {
+    String(String),
+    Float64(f64),
+    Int64(i64),
+    Uint64(u64),
+    Bool(bool),
+}
+
+impl Tag {
+    fn value(&self) -> Value<'_> {
+        match *self {
+            Tag::String(ref s) => Value::String(s),
+            Tag::Float64(x) => Value::Float64(x),
+            Tag::Int64(x) => Value::Int64(x),
+            Tag::Uint64(x) => Value::Uint64(x),
+            Tag::Bool(x) => Value::Bool(x),
+        }
+    }
+}
+
+/// A projected GeoJSON feature.
+#[derive(Debug)]
+struct Feature {
+    id: Option<u64>,
+    geometry: Geometry,
+    tags: Vec<(String, Tag)>,
+}
+
+/// The tiles cut from a GeoJSON document.
+#[derive(Debug)]
+pub struct Tiles {
+    /// The encoded tiles sorted by zoom level, x and y.
+    pub tiles: Vec<(TileAddress, Vec<u8>)>,
+    /// Problems that did not stop cutting: ids that can not be stored
+    /// in a vector tile and were dropped.
+    pub warnings: Vec<Error>,
+}
+
+/// Cuts a GeoJSON document into tiles.
+///
+/// The document may be a feature collection, a feature or a geometry.
+/// Tiles without features are left out.
+pub fn tile_geojson(geojson: &str, options: &TileOptions) -> Result<Tiles> {
+    let json: Json = serde_json::from_str(geojson).map_err(Error::Json)?;
+    let mut warnings = Vec::new();
+    let features = parse_features(&json, &options.layer, &mut warnings)?;
+    let clipped: Vec<_> = features.iter().map(|feature| feature.geometry.clone()).enumerate().collect();
+    let mut tiles = Vec::new();
+    tile_pyramid(&features, &clipped, TileAddress { z: 0, x: 0, y: 0 }, options, &mut tiles)?;
+    tiles.sort_by_key(|&(address, _)| (address.z, address.x, address.y));
+    Ok(Tiles { tiles, warnings })
+}
+
+/// Creates the tile at `address` and the tiles below it.
+///
+/// `parent` holds the index and geometry of the features clipped to
+/// the parent tile, a tile is only cut if some of them touch it.
+fn tile_pyramid(features: &[Feature], parent: &[(usize, Geometry)], address: TileAddress,
+                options: &TileOptions, tiles: &mut Vec<(TileAddress, Vec<u8>)>) -> Result<()> {
+    let size = (1u64 << address.z) as f64;
+    let buffer = options.buffer as f64 / options.extent as f64;
+    let (x, y) = (address.x as f64, address.y as f64);
+    let rect = ((x - buffer) / size, (y - buffer) / size,
+                (x + 1.0 + buffer) / size, (y + 1.0 + buffer) / size);
+    let clipped: Vec<_> = parent.iter()
+        .filter_map(|&(index, ref geometry)| Some((index, clip_geometry(geometry, rect)?)))
+        .collect();
+    if clipped.is_empty() {
+        return Ok(());
+    }
+    if address.z >= options.min_zoom {
+        let tolerance = options.tolerance / (options.extent as f64 * size);
+        let mut layer = LayerBuilder::new(&options.layer, options.extent);
+        for &(index, ref geometry) in &clipped {
+            let geometry = match simplify_geometry(geometry, tolerance) {
+                Some(geometry) => geometry,
+                None => continue,
+            };
+            let feature = &features[index];
+            let tags: Vec<_> = feature.tags.iter()
+                .map(|(key, tag)| (key.as_str(), tag.value()))
+                .collect();
+            // Features that collapse to a point or line after quantization are dropped.
+            let quantized = quantize(&geometry, address, options.extent);
+            match quantized.and_then(|geometry| layer.add_feature(feature.id, &geometry, &tags)) {
+                Ok(()) | Err(Error::InvalidGeometry(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        if !layer.is_empty() {
+            let mut tile = TileBuilder::new();
+            tile.add_layer(layer);
+            tiles.push((address, tile.to_bytes()?));
+        }
+    }
+    if address.z < options.max_zoom.min(31) {
+        for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let child = TileAddress { z: address.z + 1, x: address.x * 2 + dx, y: address.y * 2 + dy };
+            tile_pyramid(features, &clipped, child, options, tiles)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_features(json: &Json, layer: &str, warnings: &mut Vec<Error>) -> Result<Vec<Feature>> {
+    let object = json.as_object().ok_or(Error::InvalidGeoJson("expected an object"))?;
+    let mut features = Vec::new();
+    match object.get("type").and_then(Json::as_str) {
+        Some("FeatureCollection") => {
+            let members = object.get("features").and_then(Json::as_array)
+                .ok_or(Error::InvalidGeoJson("a feature collection needs features"))?;
+            for (index, member) in members.iter().enumerate() {
+                let member = member.as_object()
+                    .ok_or(Error::InvalidGeoJson("a feature must be an object"))?;
+                if let Some(warning) = parse_feature(member, &mut features)? {
+                    warnings.push(warning.in_feature(layer, index));
+                }
+            }
+        },
+        Some("Feature") => {
+            if let Some(warning) = parse_feature(object, &mut features)? {
+                warnings.push(warning.in_feature(layer, 0));
+            }
+        },
+        _ => {
+            for geometry in parse_geometry(json)? {
+                features.push(Feature { id: None, geometry, tags: Vec::new() });
+            }
+        },
+    }
+    Ok(features)
+}
+
+/// Parses a feature; geometry collections become one feature per geometry.
+///
+/// Returns a warning if the id was dropped as vector tiles only
+/// allow unsigned integers.
+fn parse_feature(object: &Map<String, Json>, features: &mut Vec<Feature>) -> Result<Option<Error>> {
+    let geometry = match object.get("geometry") {
+        Some(&Json::Null) | None => return Ok(None),
+        Some(geometry) => geometry,
+    };
+    let (id, warning) = match object.get("id") {
+        Some(&Json::Null) | None => (None, None),
+        Some(id) => match id.as_u64() {
+            Some(id) => (Some(id), None),
+            None => (None, Some(Error::UnsupportedId(id.to_string()))),
+        },
+    };
+    let mut tags = Vec::new();
+    if let Some(properties) = object.get("properties").and_then(Json::as_object) {
+        for (key, value) in properties {
+            let tag = match *value {
+                Json::Null => continue,
+                Json::Bool(b) => Tag::Bool(b),
+                Json::Number(ref n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                    (Some(n), _, _) => Tag::Uint64(n),
+                    (_, Some(n), _) => Tag::Int64(n),
+                    (_, _, Some(n)) => Tag::Float64(n),
+                    _ => continue,
+                },
+                Json::String(ref s) => Tag::String(s.clone()),
+                // Vector tiles have no nested values, keep them as JSON text.
+                Json::Array(_) | Json::Object(_) => Tag::String(value.to_string()),
+            };
+            tags.push((key.clone(), tag));
+        }
+    }
+    for geometry in parse_geometry(geometry)? {
+        features.push(Feature { id, geometry, tags: tags.clone() });
+    }
+    Ok(warning)
+}
+
+fn parse_geometry(json: &Json) -> Result<Vec<Geometry>> {
+    let object = json.as_object().ok_or(Error::InvalidGeoJson("a geometry must be an object"))?;
+    let geom_type = object.get("type").and_then(Json::as_str)
+        .ok_or(Error::InvalidGeoJson("a geometry needs a type"))?;
+    if geom_type == "GeometryCollection" {
+        let members = object.get("geometries").and_then(Json::as_array)
+            .ok_or(Error::InvalidGeoJson("a geometry collection needs geometries"))?;
+        let mut geometries = Vec::new();
+        for member in members {
+            geometries.extend(parse_geometry(member)?);
+        }
+        return Ok(geometries);
+    }
+    let coordinates = object.get("coordinates")
+        .ok_or(Error::InvalidGeoJson("a geometry needs coordinates"))?;
+    Ok(vec![match geom_type {
+        "Point" => Geometry::Points(vec![position(coordinates)?]),
+        "MultiPoint" => Geometry::Points(positions(coordinates)?),
+        "LineString" => Geometry::Lines(vec![positions(coordinates)?]),
+        "MultiLineString" => Geometry::Lines(nested(coordinates, positions)?),
+        "Polygon" => Geometry::Polygons(vec![nested(coordinates, positions)?]),
+        "MultiPolygon" => Geometry::Polygons(
+            nested(coordinates, |rings| nested(rings, positions))?),
+        _ => return Err(Error::InvalidGeoJson("unknown geometry type")),
+    }])
+}
+
+fn nested<T, F>(json: &Json, f: F) -> Result<Vec<T>>
+    where F: Fn(&Json) -> Result<T>
+{
+    json.as_array().ok_or(Error::InvalidGeoJson("coordinates must be arrays"))?
+        .iter().map(f).collect()
+}
+
+fn positions(json: &Json) -> Result<Vec<Position>> {
+    nested(json, position)
+}
+
+/// Projects a longitude and latitude to a fraction of the world.
+fn position(json: &Json) -> Result<Position> {
+    let error = Error::InvalidGeoJson("a position needs a longitude and a latitude");
+    let (lon, lat) = match json.as_array().map(Vec::as_slice) {
+        Some([lon, lat, ..]) => match (lon.as_f64(), lat.as_f64()) {
+            (Some(lon), Some(lat)) => (lon, lat),
+            _ => return Err(error),
+        },
+        _ => return Err(error),
+    };
+    let (mx, my) = lon_lat_to_mercator(lon, lat);
+    Ok(((mx / MERCATOR_MAX + 1.0) / 2.0, (1.0 - my / MERCATOR_MAX) / 2.0))
+}
+
+/// Simplifies lines and rings, rings that become too small are removed.
+fn simplify_geometry(geometry: &Geometry, tolerance: f64) -> Option<Geometry> {
+    let geometry = match *geometry {
+        Geometry::Points(ref points) => Geometry::Points(points.clone()),
+        Geometry::Lines(ref lines) => Geometry::Lines(
+            lines.iter().map(|line| simplify(line, tolerance)).filter(|l| l.len() >= 2).collect()),
+        Geometry::Polygons(ref polygons) => Geometry::Polygons(polygons.iter()
+            .map(|rings| simplify_rings(rings, |ring| Some(simplify(ring, tolerance))))
+            .filter(|rings| !rings.is_empty())
+            .collect()),
+    };
+    non_empty(geometry)
+}
+
+/// Applies `f` to all rings of a polygon.
+///
+/// Rings with less than four positions are removed and if the
+/// exterior ring is removed so is the whole polygon.
+fn simplify_rings<F>(rings: &[Vec<Position>], f: F) -> Vec<Vec<Position>>
+    where F: Fn(&[Position]) -> Option<Vec<Position>>
+{
+    let mut result = Vec::with_capacity(rings.len());
+    for (i, ring) in rings.iter().enumerate() {
+        match f(ring) {
+            Some(ring) if ring.len() >= 4 => result.push(ring),
+            _ if i == 0 => return Vec::new(),
+            _ => (),
+        }
+    }
+    result
+}
+
+fn non_empty(geometry: Geometry) -> Option<Geometry> {
+    let empty = match geometry {
+        Geometry::Points(ref points) => points.is_empty(),
+        Geometry::Lines(ref lines) => lines.is_empty(),
+        Geometry::Polygons(ref polygons) => polygons.is_empty(),
+    };
+    if empty { None } else { Some(geometry) }
+}
+
+/// Simplifies a line with the Douglas-Peucker algorithm.
+fn simplify(line: &[Position], tolerance: f64) -> Vec<Position> {
+    if line.len() <= 2 || tolerance <= 0.0 {
+        return line.to_vec();
+    }
+    let mut keep = vec![false; line.len()];
+    keep[0] = true;
+    keep[line.len() - 1] = true;
+    let mut stack = vec![(0, line.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut max = (0.0, first);
+        for i in first + 1..last {
+            let distance = segment_distance(line[i], line[first], line[last]);
+            if distance > max.0 {
+                max = (distance, i);
+            }
+        }
+        if max.0 > tolerance {
+            keep[max.1] = true;
+            stack.push((first, max.1));
+            stack.push((max.1, last));
+        }
+    }
+    line.iter().zip(keep).filter(|&(_, keep)| keep).map(|(&p, _)| p).collect()
+}
+
+/// The distance of `p` to the segment from `a` to `b`.
+fn segment_distance(p: Position, a: Position, b: Position) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (x, y) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+    (x * x + y * y).sqrt()
+}
+
+/// A rectangle as (west, north, east, south).
+type Rect = (f64, f64, f64, f64);
+
+/// Clips a geometry to a rectangle.
+///
+/// Returns `None` if nothing of the geometry is inside.
+fn clip_geometry(geometry: &Geometry, rect: Rect) -> Option<Geometry> {
+    let inside = |&(x, y): &Position| x >= rect.0 && y >= rect.1 && x <= rect.2 && y <= rect.3;
+    let geometry = match *geometry {
+        Geometry::Points(ref points) =>
+            Geometry::Points(points.iter().cloned().filter(inside).collect()),
+        Geometry::Lines(ref lines) =>
+            Geometry::Lines(lines.iter().flat_map(|line| clip_line(line, rect)).collect()),
+        Geometry::Polygons(ref polygons) => Geometry::Polygons(polygons.iter()
+            .map(|rings| simplify_rings(rings, |ring| clip_ring(ring, rect)))
+            .filter(|rings| !rings.is_empty())
+            .collect()),
+    };
+    non_empty(geometry)
+}
+
+/// Clips a line to a rectangle, parts outside split the line.
+fn clip_line(line: &[Position], rect: Rect) -> Vec<Vec<Position>> {
+    let mut parts = Vec::new();
+    let mut part: Vec<Position> = Vec::new();
+    for segment in line.windows(2) {
+        if let Some((a, b)) = clip_segment(segment[0], segment[1], rect) {
+            if part.last() != Some(&a) {
+                if part.len() >= 2 {
+                    parts.push(part);
+                }
+                part = vec![a];
+            }
+            part.push(b);
+        }
+    }
+    if part.len() >= 2 {
+        parts.push(part);
+    }
+    parts
+}
+
+/// Clips a segment with the Liang-Barsky algorithm.
+fn clip_segment(a: Position, b: Position, rect: Rect) -> Option<(Position, Position)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0f64, 1f64);
+    for &(p, q) in &[(-dx, a.0 - rect.0), (dx, rect.2 - a.0), (-dy, a.1 - rect.1), (dy, rect.3 - a.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let at = |t: f64| if t == 0.0 { a } else if t == 1.0 { b } else { (a.0 + t * dx, a.1 + t * dy) };
+    Some((at(t0), at(t1)))
+}
+
+/// Clips a closed ring with the Sutherland-Hodgman algorithm.
+fn clip_ring(ring: &[Position], rect: Rect) -> Option<Vec<Position>> {
+    // Each edge keeps the positions where `inside` is true.
+    let edges: [&dyn Fn(Position) -> f64; 4] = [
+        &|p| p.0 - rect.0, &|p| rect.2 - p.0, &|p| p.1 - rect.1, &|p| rect.3 - p.1,
+    ];
+    let mut ring = ring.to_vec();
+    for edge in &edges {
+        if ring.is_empty() {
+            return None;
+        }
+        let mut clipped = Vec::with_capacity(ring.len());
+        for segment in ring.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            let (da, db) = (edge(a), edge(b));
+            if da >= 0.0 {
+                clipped.push(a);
+            }
+            if (da >= 0.0) != (db >= 0.0) {
+                let t = da / (da - db);
+                clipped.push((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)));
+            }
+        }
+        if let Some(&first) = clipped.first() {
+            clipped.push(first);
+        }
+        ring = clipped;
+    }
+    Some(ring)
+}
+
+/// Converts positions to integer coordinates of a tile.
+fn quantize(geometry: &Geometry, address: TileAddress, extent: u32) -> Result<TileGeometry> {
+    let size = (1u64 << address.z) as f64;
+    let extent = extent as f64;
+    let point = |&(x, y): &Position| (((x * size - address.x as f64) * extent).round() as i32,
+                                       ((y * size - address.y as f64) * extent).round() as i32);
+    let line = |line: &Vec<Position>| line.iter().map(point).collect::<Vec<_>>();
+    Ok(match *geometry {
+        Geometry::Points(ref points) => TileGeometry::MultiPoint(points.iter().map(point).collect()),
+        Geometry::Lines(ref lines) => TileGeometry::MultiLineString(lines.iter().map(line).collect()),
+        Geometry::Polygons(ref polygons) => TileGeometry::MultiPolygon(MultiPolygon::new(polygons.iter()
+            .map(|rings| Polygon::new(line(&rings[0]), rings[1..].iter().map(line).collect()))
+            .collect::<Result<_>>()?)),
+    })
+}
+
+#[test]
+fn test_clip() {
+    let rect = (0.0, 0.0, 1.0, 1.0);
+    assert_eq!(clip_line(&[(-1.0, 0.5), (0.5, 0.5), (0.5, 2.0), (0.7, 2.0), (0.7, 0.5)], rect),
+               vec![vec![(0.0, 0.5), (0.5, 0.5), (0.5, 1.0)], vec![(0.7, 1.0), (0.7, 0.5)]]);
+    let square = [(-1.0, -1.0), (0.5, -1.0), (0.5, 0.5), (-1.0, 0.5), (-1.0, -1.0)];
+    assert_eq!(clip_ring(&square, rect).unwrap(),
+               vec![(0.5, 0.0), (0.5, 0.5), (0.0, 0.5), (0.0, 0.0), (0.5, 0.0)]);
+    let outside = [(2.0, 2.0), (3.0, 2.0), (3.0, 3.0), (2.0, 2.0)];
+    assert_eq!(clip_geometry(&Geometry::Polygons(vec![vec![outside.to_vec()]]), rect), None);
+    assert_eq!(simplify(&[(0.0, 0.0), (1.0, 0.01), (2.0, 0.0), (3.0, 1.0)], 0.1),
+               vec![(0.0, 0.0), (2.0, 0.0), (3.0, 1.0)]);
+}
+
+#[test]
+fn test_tile_geojson() {
+    use vector_tile::Tile;
+
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "id": 7, "properties": {"kind": "path", "min_zoom": 1, "names": ["a"]},
+         "geometry": {"type": "LineString", "coordinates": [[-90, 10], [90, 10]]}},
+        {"type": "Feature", "id": "lake", "properties": {"kind": "lake"},
+         "geometry": {"type": "Polygon", "coordinates": [[[10, 10], [20, 10], [20, 20], [10, 10]]]}},
+        {"type": "Feature", "properties": null, "geometry": null}
+    ]}"#;
+    let options = TileOptions { min_zoom: 0, max_zoom: 1, ..TileOptions::default() };
+    let Tiles { tiles, warnings } = tile_geojson(geojson, &options).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].to_string(),
+               "layer \"geojson\", feature 1: geojson: the id \"lake\" is not an unsigned integer");
+    let addresses: Vec<_> = tiles.iter().map(|&(address, _)| (address.z, address.x, address.y)).collect();
+    assert_eq!(addresses, vec![(0, 0, 0), (1, 0, 0), (1, 1, 0)]);
+
+    let tile: Tile = ::protobuf::parse_from_bytes(&tiles[0].1).unwrap();
+    let layer = &tile.get_layers()[0];
+    assert_eq!(layer.get_name(), "geojson");
+    assert_eq!(layer.get_features().len(), 2);
+    assert_eq!(layer.get_features()[0].get_id(), 7);
+    assert_eq!(layer.get_features()[0].get_geometry(), &[9, 2048, 3868, 10, 4096, 0]);
+    assert!(layer.get_values().iter().any(|v| v.get_string_value() == "[\"a\"]"));
+
+    // The line is clipped at the buffer of the tile.
+    let tile: Tile = ::protobuf::parse_from_bytes(&tiles[1].1).unwrap();
+    assert_eq!(tile.get_layers()[0].get_features()[0].get_geometry(), &[9, 4096, 7734, 10, 4224, 0]);
+    assert!(::process(tiles[2].1.as_slice()).unwrap().contains("kind-lake"));
+
+    let point = r#"{"type": "Feature", "id": -1, "properties": {},
+                    "geometry": {"type": "Point", "coordinates": [0, 0]}}"#;
+    let tiles = tile_geojson(point, &options).unwrap();
+    assert_eq!((tiles.tiles.len(), tiles.warnings.len()), (5, 1));
+
+    // Only the tiles around the point are cut at high zoom levels.
+    let options = TileOptions { min_zoom: 20, max_zoom: 20, ..TileOptions::default() };
+    assert_eq!(tile_geojson(point, &options).unwrap().tiles.len(), 4);
+
+    assert!(tile_geojson("{\"type\": \"Point\"}", &options).is_err());
+    assert!(tile_geojson("[", &options).is_err());
+}
@@ -0,0 +1,404 @@
+//! Checks tiles for compliance with the Mapbox Vector Tile specification 2.1.
+//!
+//! `process` renders what it can and skips over many problems.
+//! The validator instead reports every violation it finds together
+//! with its location and the section of the specification.
+//! Violations of MUST rules are errors, violations of SHOULD rules
+//! and other suspicious content are warnings. Rules introduced with
+//! version 2, like the winding order of rings, only warn in layers
+//! of version 1.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+
+use protobuf;
+
+use compression;
+use error::Result;
+use options::Options;
+use vector_tile::{Tile, Tile_GeomType as GeomType, Tile_Layer, Tile_Value};
+
+/// How serious a violation is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The tile violates a MUST rule of the specification.
+    Error,
+    /// The tile violates a SHOULD rule or contains suspicious data.
+    Warning,
+}
+
+/// A problem found in a tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Whether this is an error or a warning.
+    pub severity: Severity,
+    /// The index and name of the layer, if the violation is inside a layer.
+    pub layer: Option<(usize, String)>,
+    /// The index of the feature within its layer, if the violation is inside a feature.
+    pub feature: Option<usize>,
+    /// The section of the specification, e.g. "4.3.4.4".
+    pub section: &'static str,
+    /// Describes the violation.
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })?;
+        if let Some((index, ref name)) = self.layer {
+            write!(f, ": layer {} {:?}", index, name)?;
+        }
+        if let Some(index) = self.feature {
+            write!(f, ", feature {}", index)?;
+        }
+        write!(f, ": {} (spec {})", self.message, self.section)
+    }
+}
+
+/// Collects the violations while walking a tile.
+struct Report {
+    violations: Vec<Violation>,
+    layer: Option<(usize, String)>,
+    feature: Option<usize>,
+    /// The layer is version 1 which did not define all rules.
+    version_1: bool,
+}
+
+impl Report {
+    fn push(&mut self, severity: Severity, section: &'static str, message: String) {
+        self.violations.push(Violation {
+            severity,
+            layer: self.layer.clone(),
+            feature: self.feature,
+            section,
+            message,
+        });
+    }
+
+    fn error<S: Into<String>>(&mut self, section: &'static str, message: S) {
+        self.push(Severity::Error, section, message.into());
+    }
+
+    fn warning<S: Into<String>>(&mut self, section: &'static str, message: S) {
+        self.push(Severity::Warning, section, message.into());
+    }
+
+    /// Reports a violation of a rule introduced with version 2.
+    fn version_2<S: Into<String>>(&mut self, section: &'static str, message: S) {
+        let severity = if self.version_1 { Severity::Warning } else { Severity::Error };
+        self.push(severity, section, message.into());
+    }
+}
+
+/// Validates an encoded tile.
+///
+/// Gzip and zlib compressed tiles are decompressed. Fails only if
+/// the data is not a protobuf message at all.
+pub fn validate_bytes(data: &[u8]) -> Result<Vec<Violation>> {
+    validate_bytes_with_options(data, &Options::default())
+}
+
+/// Validates an encoded tile using the given options.
+///
+/// Only `decompress` applies, the validator always reports everything.
+pub fn validate_bytes_with_options(data: &[u8], options: &Options) -> Result<Vec<Violation>> {
+    let data = if options.decompress {
+        compression::decompress(data)?
+    } else {
+        Cow::Borrowed(data)
+    };
+    let tile: Tile = protobuf::parse_from_bytes(&data)?;
+    Ok(validate(&tile))
+}
+
+/// Validates a tile and returns all violations in the order they occur.
+pub fn validate(tile: &Tile) -> Vec<Violation> {
+    let mut report = Report {
+        violations: Vec::new(),
+        layer: None,
+        feature: None,
+        version_1: false,
+    };
+    let mut names = HashSet::new();
+    for (index, layer) in tile.get_layers().iter().enumerate() {
+        report.layer = Some((index, layer.get_name().to_owned()));
+        report.feature = None;
+        report.version_1 = layer.get_version() == 1;
+        if !names.insert(layer.get_name()) {
+            report.error("4.1", "the layer name is used by another layer");
+        }
+        validate_layer(layer, &mut report);
+    }
+    report.violations
+}
+
+fn validate_layer(layer: &Tile_Layer, report: &mut Report) {
+    match layer.get_version() {
+        2 => (),
+        1 => report.warning("4.1", "the layer uses version 1 of the specification"),
+        version => report.error("4.1", format!("unknown version {}", version)),
+    }
+    if !layer.has_name() || layer.get_name().is_empty() {
+        report.error("4.1", "a layer must have a name");
+    }
+    if layer.get_extent() == 0 {
+        report.error("4.1", "the extent must be greater than zero");
+    }
+    if layer.get_features().is_empty() {
+        report.warning("4.1", "a layer should contain at least one feature");
+    }
+    let mut keys = HashSet::new();
+    for key in layer.get_keys() {
+        if !keys.insert(key) {
+            report.warning("4.4", format!("the key {:?} is repeated", key));
+        }
+    }
+    for (index, value) in layer.get_values().iter().enumerate() {
+        let fields = count_value_fields(value);
+        if fields != 1 {
+            report.error("4.1", format!("value {} must have exactly one field, found {}",
+                                        index, fields));
+        }
+    }
+
+    let mut ids = HashSet::new();
+    for (index, feature) in layer.get_features().iter().enumerate() {
+        report.feature = Some(index);
+        if feature.has_id() && !ids.insert(feature.get_id()) {
+            report.warning("4.2", format!("the id {} is used by another feature", feature.get_id()));
+        }
+        let tags = feature.get_tags();
+        if tags.len() % 2 != 0 {
+            report.error("4.4", format!("the tag list has an odd length {}", tags.len()));
+        }
+        for pair in tags.chunks(2) {
+            if pair[0] as usize >= layer.get_keys().len() {
+                report.error("4.4", format!("there is no key {}", pair[0]));
+            }
+            if pair.len() == 2 && pair[1] as usize >= layer.get_values().len() {
+                report.error("4.4", format!("there is no value {}", pair[1]));
+            }
+        }
+        validate_geometry(feature.get_field_type(), feature.get_geometry(), report);
+    }
+    report.feature = None;
+}
+
+fn count_value_fields(value: &Tile_Value) -> usize {
+    [value.has_string_value(), value.has_float_value(), value.has_double_value(),
+     value.has_int_value(), value.has_uint_value(), value.has_sint_value(),
+     value.has_bool_value()].iter().filter(|&&has| has).count()
+}
+
+/// A command with the decoded positions of its parameters.
+struct Command {
+    id: u32,
+    positions: Vec<(i64, i64)>,
+}
+
+/// Splits a geometry into commands, reporting encoding errors.
+fn decode_commands(geometry: &[u32], report: &mut Report) -> Option<Vec<Command>> {
+    let mut commands = Vec::new();
+    let (mut x, mut y) = (0i64, 0i64);
+    let mut i = 0;
+    while i < geometry.len() {
+        let (id, count) = (geometry[i] & 0x7, geometry[i] >> 3);
+        i += 1;
+        let parameters = match id {
+            1 | 2 => count as usize * 2,
+            7 => 0,
+            _ => {
+                report.error("4.3.1", format!("unknown command id {}", id));
+                return None;
+            },
+        };
+        if count == 0 {
+            report.error("4.3.1", format!("command {} has a count of zero", id));
+        }
+        if id == 7 && count > 1 {
+            report.error("4.3.3.3", format!("ClosePath has a count of {}, expected 1", count));
+        }
+        if geometry.len() - i < parameters {
+            report.error("4.3.2", format!("command {} expects {} parameters, found {}",
+                                          id, parameters, geometry.len() - i));
+            return None;
+        }
+        let mut positions = Vec::with_capacity(count as usize);
+        for pair in geometry[i..i + parameters].chunks(2) {
+            let (dx, dy) = (de_zigzag(pair[0]), de_zigzag(pair[1]));
+            if id == 2 && dx == 0 && dy == 0 {
+                report.version_2("4.3.3.2", "LineTo has a zero length");
+            }
+            x += dx;
+            y += dy;
+            positions.push((x, y));
+        }
+        i += parameters;
+        commands.push(Command { id, positions });
+    }
+    Some(commands)
+}
+
+fn de_zigzag(n: u32) -> i64 {
+    ::cursor::de_zigzag(n) as i64
+}
+
+fn validate_geometry(geom_type: GeomType, geometry: &[u32], report: &mut Report) {
+    if geom_type == GeomType::UNKNOWN {
+        report.warning("4.3.4.1", "the geometry type is unknown");
+        return;
+    }
+    if geometry.is_empty() {
+        report.error("4.3.4", "the geometry is empty");
+        return;
+    }
+    let commands = match decode_commands(geometry, report) {
+        Some(commands) => commands,
+        None => return,
+    };
+    let ids: Vec<u32> = commands.iter().map(|c| c.id).collect();
+    match geom_type {
+        GeomType::POINT => {
+            if ids != [1] {
+                report.error("4.3.4.2", "a point geometry must be a single MoveTo");
+            }
+        },
+        GeomType::LINESTRING => {
+            let mut i = 0;
+            while i < commands.len() {
+                let valid = ids[i] == 1 && commands[i].positions.len() == 1
+                    && ids.get(i + 1) == Some(&2);
+                if !valid {
+                    report.error("4.3.4.3",
+                        "a linestring must consist of a MoveTo with count 1 followed by a LineTo");
+                    return;
+                }
+                i += 2;
+            }
+        },
+        GeomType::POLYGON => validate_rings(&commands, report),
+        GeomType::UNKNOWN => unreachable!(),
+    }
+}
+
+fn validate_rings(commands: &[Command], report: &mut Report) {
+    let mut rings = 0;
+    for (ring, chunk) in commands.chunks(3).enumerate() {
+        let valid = chunk.len() == 3 && chunk[0].id == 1 && chunk[0].positions.len() == 1
+            && chunk[1].id == 2 && chunk[2].id == 7;
+        if !valid {
+            report.error("4.3.4.4",
+                "a ring must consist of a MoveTo with count 1, a LineTo and a ClosePath");
+            return;
+        }
+        if chunk[1].positions.len() < 2 {
+            report.error("4.3.4.4", format!("ring {} has less than four positions", ring));
+        }
+        let positions: Vec<_> = chunk[0].positions.iter().chain(&chunk[1].positions).collect();
+        let area = match ring_area(&positions) {
+            Some(area) => area,
+            None => {
+                report.error("4.3.4.4", format!("ring {} is too large to compute its area", ring));
+                continue;
+            },
+        };
+        if area == 0 {
+            report.error("4.3.4.4", format!("ring {} has no area", ring));
+        } else if ring == 0 && area < 0 {
+            report.version_2("4.3.4.4", "the first ring must be an exterior ring (clockwise)");
+        }
+        rings += 1;
+    }
+    if rings == 0 {
+        report.error("4.3.4.4", "a polygon needs a ring");
+    }
+}
+
+/// Twice the signed area of a ring, `None` if it overflows.
+fn ring_area(positions: &[&(i64, i64)]) -> Option<i128> {
+    let mut area = 0i128;
+    for (i, a) in positions.iter().enumerate() {
+        let b = positions[(i + 1) % positions.len()];
+        let cross = i128::from(a.0).checked_mul(i128::from(b.1))?
+            .checked_sub(i128::from(b.0).checked_mul(i128::from(a.1))?)?;
+        area = area.checked_add(cross)?;
+    }
+    Some(area)
+}
+
+#[test]
+fn test_validate() {
+    use encoder::{LayerBuilder, TileBuilder};
+    use geometry::{Geometry, Polygon};
+    use protobuf::RepeatedField;
+    use tag::Value;
+    use vector_tile::Tile_Feature;
+
+    let mut roads = LayerBuilder::new("roads", 4096);
+    let tags = [("kind", Value::String("path"))];
+    roads.add_feature(Some(1), &Geometry::LineString(vec![(0, 0), (10, 10)]), &tags).unwrap();
+    roads.add_feature(Some(1), &Geometry::LineString(vec![(0, 0), (10, 10)]), &tags).unwrap();
+    let mut water = LayerBuilder::new("water", 4096);
+    let lake = Polygon::new(vec![(0, 0), (0, 10), (10, 10)], Vec::new()).unwrap();
+    water.add_feature(None, &Geometry::Polygon(lake), &tags).unwrap();
+    let mut builder = TileBuilder::new();
+    builder.add_layer(roads);
+    builder.add_layer(water);
+    let valid = builder.build();
+    let violations = validate(&valid);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].severity, Severity::Warning);
+    assert_eq!(violations[0].to_string(),
+               "warning: layer 0 \"roads\", feature 1: the id 1 is used by another feature (spec 4.2)");
+
+    let mut invalid = valid.clone();
+    let mut counter_clockwise = Tile_Feature::new();
+    counter_clockwise.set_field_type(GeomType::POLYGON);
+    counter_clockwise.set_geometry(vec![9, 0, 0, 18, 0, 20, 20, 0, 15]);
+    let mut zero_count = Tile_Feature::new();
+    zero_count.set_field_type(GeomType::POINT);
+    zero_count.set_geometry(vec![1]);
+    let mut duplicate = valid.get_layers()[1].clone();
+    duplicate.set_version(3);
+    duplicate.set_features(RepeatedField::from_vec(vec![counter_clockwise, zero_count]));
+    invalid.mut_layers().push(duplicate);
+    let violations = validate(&invalid);
+    let found: Vec<_> = violations.iter().skip(1)
+        .map(|v| (v.severity, v.feature, v.section)).collect();
+    assert_eq!(found, vec![
+        (Severity::Error, None, "4.1"),
+        (Severity::Error, None, "4.1"),
+        (Severity::Error, Some(0), "4.3.4.4"),
+        (Severity::Error, Some(1), "4.3.1"),
+    ]);
+    assert!(violations[1].message.contains("another layer"));
+    assert!(violations[2].message.contains("version 3"));
+    // The web map tile is version 1 and has zero length LineTo commands.
+    let violations = validate_bytes(include_bytes!("../web/tile.mvt")).unwrap();
+    assert!(violations.iter().any(|v| v.section == "4.3.3.2"));
+    assert!(violations.iter().all(|v| v.severity == Severity::Warning));
+    let options = Options { decompress: false, ..Options::default() };
+    assert!(validate_bytes_with_options(include_bytes!("../web/tile.mvt"), &options).is_err());
+}
+
+#[test]
+fn test_extreme_coordinates() {
+    use encoder::LayerBuilder;
+    use vector_tile::{Tile, Tile_Feature};
+
+    // A rhombus with deltas of i32::MAX, its area does not fit in an i64.
+    let max = 0xffff_fffe;
+    let mut rhombus = Tile_Feature::new();
+    rhombus.set_field_type(GeomType::POLYGON);
+    rhombus.set_geometry(vec![9, max, max, 26, max, max - 1, max, max, max - 1, max, 15]);
+    let mut raw_layer = LayerBuilder::new("water", 4096).build();
+    raw_layer.mut_features().push(rhombus);
+    let mut tile = Tile::new();
+    tile.mut_layers().push(raw_layer);
+    let violations = validate(&tile);
+    assert!(violations.iter().all(|v| v.severity != Severity::Error), "{:?}", violations);
+}